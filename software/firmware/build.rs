@@ -1,3 +1,23 @@
+use std::{
+	process::Command,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
 fn main() {
-	println!("cargo:rustc-link-arg=-Tlinkall.x");
+	println!("cargo:rustc-link-arg-bins=-Tlinkall.x");
+	// Earliest time the device clock can be set to (see src/clock.rs)
+	let build_time = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|d| d.as_secs())
+		.unwrap_or(0);
+	println!("cargo:rustc-env=BUILD_TIMESTAMP={build_time}");
+	// Shown on the diagnostics screen (see src/diagnostics.rs)
+	let build_hash = Command::new("git")
+		.args(["rev-parse", "--short", "HEAD"])
+		.output()
+		.ok()
+		.filter(|output| output.status.success())
+		.and_then(|output| String::from_utf8(output.stdout).ok())
+		.map_or_else(|| "unknown".into(), |hash| hash.trim().to_owned());
+	println!("cargo:rustc-env=BUILD_HASH={build_hash}");
 }
@@ -0,0 +1,25 @@
+//! Alarm raised when a countdown reaches zero. Stays raised until it's acknowledged with a button press
+
+use core::cell::Cell;
+
+use embassy_sync::{
+	blocking_mutex::{Mutex, raw::CriticalSectionRawMutex},
+	signal::Signal,
+};
+
+static ALARM: Mutex<CriticalSectionRawMutex, Cell<bool>> = Mutex::new(Cell::new(false));
+/// Signalled whenever the alarm is raised
+pub static ALARM_RAISED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+pub fn raise_alarm() {
+	ALARM.lock(|a| a.set(true));
+	ALARM_RAISED.signal(());
+}
+
+pub fn acknowledge_alarm() {
+	ALARM.lock(|a| a.set(false));
+}
+
+pub fn alarm_active() -> bool {
+	ALARM.lock(|a| a.get())
+}
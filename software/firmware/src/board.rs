@@ -0,0 +1,27 @@
+//! Hardware that differs between builds of the board, chosen with Cargo features.
+//! Without any, it has a 128x64 SSD1306 display
+
+use display_interface::AsyncWriteOnlyDataCommand;
+use ssd1306::prelude::DisplayRotation;
+
+use crate::panel::Panel;
+
+#[cfg(all(feature = "ssd1306-128x32", feature = "sh1106"))]
+compile_error!("Only one display can be chosen");
+
+#[cfg(feature = "ssd1306-128x32")]
+use ssd1306::size::DisplaySize128x32 as DisplaySize;
+#[cfg(not(any(feature = "ssd1306-128x32", feature = "sh1106")))]
+use ssd1306::size::DisplaySize128x64 as DisplaySize;
+
+/// Creates the display fitted to the board, which still needs setting up with `init`
+#[cfg(feature = "sh1106")]
+pub fn display<DI: AsyncWriteOnlyDataCommand>(interface: DI) -> impl Panel {
+	crate::sh1106::Sh1106::new(interface, DisplayRotation::Rotate0)
+}
+/// Creates the display fitted to the board, which still needs setting up with `init`
+#[cfg(not(feature = "sh1106"))]
+pub fn display<DI: AsyncWriteOnlyDataCommand>(interface: DI) -> impl Panel {
+	ssd1306::Ssd1306Async::new(interface, DisplaySize, DisplayRotation::Rotate0)
+		.into_buffered_graphics_mode()
+}
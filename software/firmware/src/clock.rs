@@ -0,0 +1,66 @@
+//! Keeps track of the wall-clock time
+//!
+//! The ESP32-C3 has no battery backed clock, so the time is lost whenever the device is powered off.
+//! It's unknown until it's set from the serial console, then kept as an offset from boot
+
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use embassy_time::Instant;
+
+pub const SECS_PER_DAY: u64 = 86400;
+
+/// Seconds since the unix epoch when the device booted, once the time has been set
+static BOOT_TIME: Mutex<CriticalSectionRawMutex, Cell<Option<u64>>> = Mutex::new(Cell::new(None));
+
+/// Seconds since the unix epoch when the firmware was built. The time can't be set to before this
+pub const BUILD_TIME: u64 = match u64::from_str_radix(env!("BUILD_TIMESTAMP"), 10) {
+	Ok(x) => x,
+	Err(_) => 0,
+};
+
+/// Seconds since the unix epoch at the start of 2100. The time can't be set to after this, so adding
+/// the uptime to it can't overflow
+pub const LATEST_TIME: u64 = 4_102_444_800;
+
+/// Current time in seconds since the unix epoch, or None if it hasn't been set since boot
+pub fn now() -> Option<u64> {
+	BOOT_TIME
+		.lock(|t| t.get())
+		.map(|boot| boot + Instant::now().as_secs())
+}
+
+/// Sets the current time (in seconds since the unix epoch).
+/// Returns false, leaving the clock as it was, if it's before the firmware was built or after
+/// `LATEST_TIME`, as it can't be right
+pub fn set_time(secs: u64) -> bool {
+	if !(BUILD_TIME..=LATEST_TIME).contains(&secs) {
+		return false;
+	}
+	let uptime = Instant::now().as_secs();
+	BOOT_TIME.lock(|t| t.set(Some(secs.saturating_sub(uptime))));
+	true
+}
+
+/// Number of whole days since the unix epoch
+pub const fn days_since_epoch(secs: u64) -> u64 {
+	secs / SECS_PER_DAY
+}
+
+/// Index of the month containing the given day (`year * 12 + month`, where January is 0)
+pub const fn month_index(days: u64) -> u64 {
+	// Adapted from http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+	let z = days + 719468;
+	let era = z / 146097;
+	let doe = z - era * 146097;
+	let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+	let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+	let mp = (5 * doy + 2) / 153;
+	// March is 0, so January and February belong to the following year
+	let (year, month) = if mp < 10 {
+		(yoe + era * 400, mp + 2)
+	} else {
+		(yoe + era * 400 + 1, mp - 10)
+	};
+	year * 12 + month
+}
@@ -1,29 +1,34 @@
 //! Handles serializing and deserializing how the device is configured
 //! to a single u8 value which can easily be saved to the ESP32 flash
 
-use sequential_storage::map::Value;
+use sequential_storage::map::SerializationError;
+use smart_leds::RGB8;
 
 use crate::{
+	alarm::acknowledge_alarm,
 	const_default::ConstDefault,
-	count::COUNT,
-	menustate::{ResetOptions, RgbBrightness, RgbRate},
-	tasks::handle_neopixel::{RGB_CONFIG, RgbMode},
+	count::{COUNT, CounterConfig, read_counter_config, write_counter_config},
+	display::{DisplayConfig, write_display_config},
+	menustate::ResetOptions,
+	record::{Field, Reader, Record, Writer, impl_value_as_record, read_into},
+	stats::{StatsConfig, Tallies, write_stats_config, write_tallies},
+	tasks::handle_neopixel::{RGB_CONFIG, RGB_RATES, RgbMode},
+	title::{Title, write_title},
+	toast::toast,
+	wear::{SaveConfig, write_save_config},
 };
 
-#[repr(C)]
 #[derive(Clone, Debug, PartialEq)]
 pub struct RgbConfig {
 	pub rgb_mode: RgbMode,
-	pub rgb_brightness: RgbBrightness,
-	pub rgb_rate_modifier: RgbRate,
+	/// Brightness level of the LED, out of 255
+	pub rgb_brightness: u8,
+	/// Multiplies the speed of the RGB effects
+	pub rgb_rate_modifier: u8,
 }
 
 impl RgbConfig {
-	pub fn new(
-		rgb_mode: RgbMode,
-		rgb_brightness: RgbBrightness,
-		rgb_rate_modifier: RgbRate,
-	) -> Self {
+	pub fn new(rgb_mode: RgbMode, rgb_brightness: u8, rgb_rate_modifier: u8) -> Self {
 		Self {
 			rgb_mode,
 			rgb_brightness,
@@ -39,61 +44,115 @@ impl RgbConfig {
 	pub fn set_mode(&mut self, rgb_mode: RgbMode) {
 		self.rgb_mode = rgb_mode;
 	}
-	pub fn set_brightness(&mut self, rgb_brightness: RgbBrightness) {
+	pub fn set_brightness(&mut self, rgb_brightness: u8) {
 		self.rgb_brightness = rgb_brightness;
 	}
-	pub fn set_rate(&mut self, rgb_rate_modifier: RgbRate) {
+	pub fn set_rate(&mut self, rgb_rate_modifier: u8) {
 		self.rgb_rate_modifier = rgb_rate_modifier;
 	}
 }
 impl ConstDefault for RgbConfig {
 	const DEFAULT: Self = Self {
 		rgb_mode: RgbMode::DEFAULT,
-		rgb_brightness: RgbBrightness::DEFAULT,
-		rgb_rate_modifier: RgbRate::DEFAULT,
+		rgb_brightness: 10,
+		rgb_rate_modifier: 7,
 	};
 }
-impl<'a> Value<'a> for RgbConfig {
-	fn serialize_into(
-		&self,
-		buffer: &mut [u8],
-	) -> Result<usize, sequential_storage::map::SerializationError> {
-		buffer[..core::mem::size_of::<Self>()].copy_from_slice(unsafe {
-			core::slice::from_raw_parts(
-				(self as *const Self) as *const u8,
-				core::mem::size_of::<Self>(),
-			)
-		});
-		Ok(core::mem::size_of::<Self>())
+impl Record for RgbConfig {
+	const VERSION: u8 = 1;
+	fn write_fields(&self, w: &mut Writer) {
+		self.rgb_mode.write(w);
+		self.rgb_brightness.write(w);
+		self.rgb_rate_modifier.write(w);
 	}
-	fn deserialize_from(
-		buffer: &'a [u8],
-	) -> Result<Self, sequential_storage::map::SerializationError>
-	where
-		Self: Sized,
-	{
-		let data = unsafe {
-			&*buffer[..core::mem::size_of::<Self>()]
-				.as_ptr()
-				.cast::<Self>()
+	fn read_fields(&mut self, version: u8, r: &mut Reader) -> Result<(), SerializationError> {
+		if version != Self::VERSION {
+			return Err(SerializationError::InvalidFormat);
+		}
+		read_into(&mut self.rgb_mode, r);
+		read_into(&mut self.rgb_brightness, r);
+		// The effects divide by the rate, so one that's out of range is left at the default
+		let mut rate = self.rgb_rate_modifier;
+		read_into(&mut rate, r);
+		if RGB_RATES.contains(&(rate as u32)) {
+			self.rgb_rate_modifier = rate;
+		}
+		Ok(())
+	}
+}
+impl_value_as_record!(RgbConfig);
+
+/// Stored as a byte for the mode, then 4 for its parameter.
+/// A rate outside `RGB_RATES` isn't read, so the mode is left as it was
+impl Field for RgbMode {
+	fn write(&self, w: &mut Writer) {
+		let (mode, parameter) = match self {
+			RgbMode::SineCycle(step) => (0u8, step.to_bits()),
+			RgbMode::Continuous(x) => (1, *x),
+			RgbMode::Random(x) => (2, *x),
+			RgbMode::Fibonacci(x) => (3, *x),
+			RgbMode::Static(c) => (4, u32::from_le_bytes([c.r, c.g, c.b, 0])),
+		};
+		mode.write(w);
+		parameter.write(w);
+	}
+	fn read(r: &mut Reader) -> Option<Self> {
+		let mode = u8::read(r);
+		let parameter = u32::read(r)?;
+		match mode? {
+			0 => Some(f32::from_bits(parameter))
+				.filter(|step| step.is_finite())
+				.map(RgbMode::SineCycle),
+			1..=3 if !RGB_RATES.contains(&parameter) => None,
+			1 => Some(RgbMode::Continuous(parameter)),
+			2 => Some(RgbMode::Random(parameter)),
+			3 => Some(RgbMode::Fibonacci(parameter)),
+			4 => {
+				let [r, g, b, _] = parameter.to_le_bytes();
+				Some(RgbMode::Static(RGB8::new(r, g, b)))
+			}
+			_ => None,
 		}
-		.clone();
-		Ok(data)
 	}
 }
+
 async fn reset_rgb_config() {
 	RgbConfig::DEFAULT.apply().await
 }
 fn reset_count() {
-	COUNT.sender().send(0)
+	acknowledge_alarm();
+	COUNT.sender().send(read_counter_config().initial_count())
+}
+fn reset_counter_config() {
+	write_counter_config(CounterConfig::DEFAULT);
+}
+fn reset_save_config() {
+	write_save_config(SaveConfig::DEFAULT);
+}
+fn reset_display_config() {
+	write_display_config(DisplayConfig::DEFAULT);
+}
+fn reset_title() {
+	write_title(Title::DEFAULT);
+}
+fn reset_stats() {
+	write_tallies(Tallies::DEFAULT);
+	write_stats_config(StatsConfig::DEFAULT);
 }
 pub async fn reset(opt: ResetOptions) {
 	match opt {
 		ResetOptions::All => {
 			reset_rgb_config().await;
+			reset_counter_config();
+			reset_save_config();
+			reset_display_config();
+			reset_title();
 			reset_count();
+			reset_stats();
 		}
 		ResetOptions::Count => reset_count(),
 		ResetOptions::Rgb => reset_rgb_config().await,
+		ResetOptions::Stats => reset_stats(),
 	}
+	toast("Reset done");
 }
@@ -1,28 +1,300 @@
-use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, watch::Watch};
+use core::cell::Cell;
 
-pub static COUNT: Watch<CriticalSectionRawMutex, u32, 2> = Watch::new();
+use embassy_sync::{
+	blocking_mutex::{Mutex, raw::CriticalSectionRawMutex},
+	watch::Watch,
+};
+use sequential_storage::map::{SerializationError, Value};
+use strum::{IntoStaticStr, VariantArray};
 
-pub fn read_count() -> u32 {
+use crate::{
+	alarm::raise_alarm,
+	const_default::ConstDefault,
+	highlight::{Highlight, highlight},
+	record::{Field, Reader, Record, Writer, impl_field_for_enum, impl_value_as_record, read_into},
+	stats,
+	toast::toast,
+};
+
+/// Wide enough to hold the full range of both signed (`i64`) and unsigned (`u64`) counters
+pub type Count = i128;
+
+pub static COUNT: Watch<CriticalSectionRawMutex, Count, 2> = Watch::new();
+
+/// The count as older firmware stored it, which is only ever read to carry it over
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LegacyCount(pub u32);
+impl<'a> Value<'a> for LegacyCount {
+	fn serialize_into(&self, buffer: &mut [u8]) -> Result<usize, SerializationError> {
+		self.0.serialize_into(buffer)
+	}
+	fn deserialize_from(buffer: &'a [u8]) -> Result<Self, SerializationError> {
+		u32::deserialize_from(buffer).map(Self)
+	}
+}
+impl From<LegacyCount> for Count {
+	fn from(LegacyCount(count): LegacyCount) -> Self {
+		count.into()
+	}
+}
+
+/// The count as it's stored, so that no other `Count` can be saved in its place
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StoredCount(pub Count);
+impl<'a> Value<'a> for StoredCount {
+	fn serialize_into(&self, buffer: &mut [u8]) -> Result<usize, SerializationError> {
+		self.0.serialize_into(buffer)
+	}
+	fn deserialize_from(buffer: &'a [u8]) -> Result<Self, SerializationError> {
+		Count::deserialize_from(buffer).map(Self)
+	}
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, IntoStaticStr, VariantArray, PartialEq)]
+pub enum Direction {
+	#[strum(serialize = "Count Up")]
+	Up,
+	/// Count down from `CounterConfig::start`, and raise the alarm when zero is reached
+	#[strum(serialize = "Count Down")]
+	Down,
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, IntoStaticStr, VariantArray, PartialEq)]
+pub enum Signedness {
+	/// Counts from 0 to `u64::MAX`
+	#[strum(serialize = "Positive")]
+	Unsigned,
+	/// Counts from `i64::MIN` to `i64::MAX`
+	#[strum(serialize = "Negative OK")]
+	Signed,
+}
+
+/// What happens when the count would go past the end of its range
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, IntoStaticStr, VariantArray, PartialEq)]
+pub enum OverflowPolicy {
+	/// Stop at the end of the range
+	Saturate,
+	/// Carry on from the other end of the range
+	Wrap,
+	/// Leave the count unchanged
+	Refuse,
+}
+
+/// Settings for the counter, saved alongside the count
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CounterConfig {
+	/// Count to aim for. 0 if there's no target. Only used when counting up
+	pub target: u32,
+	/// Celebrate every time the count reaches a multiple of this. 0 to disable.
+	/// Only used when counting up
+	pub milestone: u32,
+	pub direction: Direction,
+	/// Sound the buzzer as well as flashing when the alarm is raised
+	pub buzzer: bool,
+	pub signedness: Signedness,
+	pub overflow: OverflowPolicy,
+	/// Value that a countdown starts from
+	pub start: u32,
+	/// Amount the count changes by with each press
+	pub step: u32,
+}
+impl ConstDefault for CounterConfig {
+	const DEFAULT: Self = Self {
+		target: 0,
+		milestone: 0,
+		direction: Direction::Up,
+		buzzer: false,
+		signedness: Signedness::Unsigned,
+		overflow: OverflowPolicy::Saturate,
+		start: 10,
+		step: 1,
+	};
+}
+impl CounterConfig {
+	/// Value that the count is set to when it's reset
+	pub fn initial_count(&self) -> Count {
+		match self.direction {
+			Direction::Up => 0,
+			Direction::Down => self.start as Count,
+		}
+	}
+	/// Smallest and largest values the count can take
+	pub fn range(&self) -> (Count, Count) {
+		match self.signedness {
+			Signedness::Unsigned => (0, u64::MAX as Count),
+			Signedness::Signed => (i64::MIN as Count, i64::MAX as Count),
+		}
+	}
+}
+impl_field_for_enum!(Direction, Signedness, OverflowPolicy);
+impl Record for CounterConfig {
+	const VERSION: u8 = 2;
+	fn write_fields(&self, w: &mut Writer) {
+		self.target.write(w);
+		self.milestone.write(w);
+		self.direction.write(w);
+		self.buzzer.write(w);
+		self.signedness.write(w);
+		self.overflow.write(w);
+		self.start.write(w);
+		self.step.write(w);
+	}
+	fn read_fields(&mut self, version: u8, r: &mut Reader) -> Result<(), SerializationError> {
+		// Version 1 ended with the save policy, which is now saved on its own (see `SaveConfig`).
+		// It's left unread, so it goes back to the default
+		if !matches!(version, 1 | 2) {
+			return Err(SerializationError::InvalidFormat);
+		}
+		read_into(&mut self.target, r);
+		read_into(&mut self.milestone, r);
+		read_into(&mut self.direction, r);
+		read_into(&mut self.buzzer, r);
+		read_into(&mut self.signedness, r);
+		read_into(&mut self.overflow, r);
+		read_into(&mut self.start, r);
+		read_into(&mut self.step, r);
+		Ok(())
+	}
+}
+impl_value_as_record!(CounterConfig);
+
+// Blocking mutex so the config can be read when the count changes
+pub static COUNTER_CONFIG: Mutex<CriticalSectionRawMutex, Cell<CounterConfig>> =
+	Mutex::new(Cell::new(CounterConfig::DEFAULT));
+
+pub fn read_counter_config() -> CounterConfig {
+	COUNTER_CONFIG.lock(|c| c.get())
+}
+
+pub fn write_counter_config(config: CounterConfig) {
+	COUNTER_CONFIG.lock(|c| c.set(config))
+}
+
+pub fn read_count() -> Count {
 	COUNT.try_get().unwrap_or(0)
 }
 
-pub fn write_count(x: u32) {
+pub fn write_count(x: Count) {
 	let snd = COUNT.sender();
 	snd.send(x);
 }
 
-pub fn increase_count(x: i32) {
-	let new = (COUNT.try_get().unwrap_or(0) as i32).wrapping_add(x);
-	if new < 0 {
+/// Outcome of trying to change the count
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CountChange {
+	Changed,
+	/// Went past the end of the range, so stopped at the end
+	Saturated,
+	/// Went past the end of the range, so carried on from the other end
+	Wrapped,
+	/// Would have gone past the end of the range, so wasn't changed
+	Refused,
+}
+
+/// Changes the count by `x`, following the counter's overflow policy at the ends of its range
+pub fn increase_count(x: Count) -> CountChange {
+	let config = read_counter_config();
+	let (min, max) = config.range();
+	// Can't overflow, as the count and `x` are both much smaller than `Count::MAX`
+	let new = read_count() + x;
+	let (new, change) = if (min..=max).contains(&new) {
+		(new, CountChange::Changed)
+	} else {
+		match config.overflow {
+			OverflowPolicy::Saturate => (new.clamp(min, max), CountChange::Saturated),
+			OverflowPolicy::Wrap => (
+				min + (new - min).rem_euclid(max - min + 1),
+				CountChange::Wrapped,
+			),
+			OverflowPolicy::Refuse => return CountChange::Refused,
+		}
+	};
+	write_count(new);
+	change
+}
+
+/// Brings the count back within range after the counter's config has changed
+pub fn fit_count_to_range() {
+	let (min, max) = read_counter_config().range();
+	let count = read_count();
+	if !(min..=max).contains(&count) {
+		write_count(count.clamp(min, max));
+	}
+}
+
+/// Moves the count one step in the counter's direction (in response to a button press)
+pub fn advance_count() {
+	let config = read_counter_config();
+	let old = read_count();
+	let step = config.step as Count;
+	let change = match config.direction {
+		Direction::Up => increase_count(step),
+		Direction::Down => {
+			// A countdown stops at zero. The alarm has already been raised when it got there
+			if old <= 0 {
+				return;
+			}
+			increase_count(-step.min(old))
+		}
+	};
+	let new = read_count();
+	if new == old {
+		highlight(Highlight::Limit);
+		return;
+	}
+	stats::record(moved(change, step, old, new));
+	match config.direction {
+		Direction::Up => check_milestones(&config, old, new),
+		Direction::Down => {
+			if new == 0 {
+				raise_alarm();
+			}
+		}
+	}
+}
+
+/// Undoes a step made by `advance_count`
+pub fn retreat_count() {
+	let config = read_counter_config();
+	let old = read_count();
+	let step = config.step as Count;
+	let change = match config.direction {
+		Direction::Up => increase_count(-step),
+		Direction::Down => increase_count(step),
+	};
+	let new = read_count();
+	if new == old {
+		highlight(Highlight::Limit);
 		return;
 	}
-	write_count(new as u32);
+	stats::record(-moved(change, step, old, new));
 }
 
-pub fn increment_count() {
-	increase_count(1);
+/// How far the count actually moved, which is less than the step if it stopped at the end of its
+/// range. Wrapping around counts as the whole step
+fn moved(change: CountChange, step: Count, old: Count, new: Count) -> i64 {
+	let moved = match change {
+		CountChange::Wrapped => step,
+		_ => (new - old).abs(),
+	};
+	// No more than the step, which is a `u32`
+	moved as i64
 }
 
-pub fn decrement_count() {
-	increase_count(-1);
+/// Celebrates if the count has just gone past the target or a milestone
+fn check_milestones(config: &CounterConfig, old: Count, new: Count) {
+	if new <= old {
+		return;
+	}
+	let target = config.target as Count;
+	let milestone = config.milestone as Count;
+	if target != 0 && old < target && new >= target {
+		highlight(Highlight::Goal);
+		toast("Goal reached");
+	} else if milestone != 0 && new.div_euclid(milestone) > old.div_euclid(milestone) {
+		highlight(Highlight::Milestone(new - new.rem_euclid(milestone)));
+	}
 }
@@ -0,0 +1,134 @@
+//! Information about the health of the device, shown on the diagnostics screen
+
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use embassy_time::{Duration, Instant};
+#[cfg(feature = "device")]
+use esp_hal::{rtc_cntl::reset_reason, system::Cpu};
+
+use crate::{
+	const_default::ConstDefault,
+	count::{Count, read_count},
+	wear::Wear,
+};
+
+/// Version of the firmware, from Cargo.toml
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Commit the firmware was built from (see build.rs)
+pub const BUILD: &str = env!("BUILD_HASH");
+
+/// Counters kept since boot
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Diagnostics {
+	/// Items written to flash
+	pub storage_writes: u32,
+	/// Times reading or writing flash failed, even after retrying
+	pub storage_errors: u32,
+	/// Button events that were replaced by another before they were handled
+	pub button_drops: u32,
+	/// The count as it was last saved to flash
+	pub stored_count: Option<Count>,
+	/// Bytes of the storage region that have been written to, once it's been checked
+	pub flash_used: Option<u32>,
+	/// Size of the storage region in bytes
+	pub flash_size: u32,
+	/// Size of the NVS partition that the storage region is at the start of, once it's been found
+	pub partition_size: Option<u32>,
+	/// Erases and writes of each page of the storage region
+	pub wear: Wear,
+	/// How long the count waits to be saved after it changes
+	pub save_delay: Duration,
+}
+
+static DIAGNOSTICS: Mutex<CriticalSectionRawMutex, Cell<Diagnostics>> =
+	Mutex::new(Cell::new(Diagnostics {
+		storage_writes: 0,
+		storage_errors: 0,
+		button_drops: 0,
+		stored_count: None,
+		flash_used: None,
+		flash_size: 0,
+		partition_size: None,
+		wear: Wear::DEFAULT,
+		save_delay: Duration::from_secs(0),
+	}));
+
+pub fn read_diagnostics() -> Diagnostics {
+	DIAGNOSTICS.lock(|d| d.get())
+}
+
+pub fn update_diagnostics(f: impl FnOnce(&mut Diagnostics)) {
+	DIAGNOSTICS.lock(|d| {
+		let mut diagnostics = d.get();
+		f(&mut diagnostics);
+		d.set(diagnostics);
+	})
+}
+
+/// Why the chip was last reset, formatted into `buf`
+#[cfg(feature = "device")]
+fn reset_cause(buf: &mut [u8]) -> Option<&str> {
+	let reason = reset_reason(Cpu::ProCpu)?;
+	format_no_std::show(buf, format_args!("Reset {reason:?}")).ok()
+}
+/// Off the device there's no reset reason to read
+#[cfg(not(feature = "device"))]
+fn reset_cause(_buf: &mut [u8]) -> Option<&str> {
+	None
+}
+
+/// Number of lines on the diagnostics screen
+pub const LINES: usize = 13;
+
+/// Formats line `i` of the diagnostics screen into `buf`
+pub fn line(i: usize, buf: &mut [u8]) -> &str {
+	let d = read_diagnostics();
+	let uptime = Instant::now().as_secs();
+	let result = match i {
+		0 => format_no_std::show(buf, format_args!("Firmware v{VERSION}")),
+		1 => format_no_std::show(buf, format_args!("Build {BUILD}")),
+		2 => format_no_std::show(
+			buf,
+			format_args!(
+				"Up {}d {:02}:{:02}:{:02}",
+				uptime / 86400,
+				uptime / 3600 % 24,
+				uptime / 60 % 60,
+				uptime % 60
+			),
+		),
+		3 => Ok(reset_cause(buf).unwrap_or("Reset unknown")),
+		4 => match d.flash_used {
+			Some(used) => {
+				format_no_std::show(buf, format_args!("Region {used}/{} B", d.flash_size))
+			}
+			None => Ok("Region not checked"),
+		},
+		// Only the region at the start of the partition is written to, so the rest of it is free
+		5 => match (d.flash_used, d.partition_size) {
+			(Some(used), Some(size)) => format_no_std::show(
+				buf,
+				format_args!("NVS free {} B", size.saturating_sub(used)),
+			),
+			_ => Ok("NVS free unknown"),
+		},
+		6 => format_no_std::show(buf, format_args!("Writes {}", d.storage_writes)),
+		7 => format_no_std::show(buf, format_args!("Most erases {}", d.wear.most_erases())),
+		8 => {
+			let left = d.wear.permille_left();
+			format_no_std::show(buf, format_args!("Life left {}.{}%", left / 10, left % 10))
+		}
+		9 => format_no_std::show(buf, format_args!("Save delay {}s", d.save_delay.as_secs())),
+		10 => format_no_std::show(buf, format_args!("Storage errors {}", d.storage_errors)),
+		11 => match d.stored_count {
+			Some(stored) => {
+				format_no_std::show(buf, format_args!("Count {} saved {stored}", read_count()))
+			}
+			None => format_no_std::show(buf, format_args!("Count {} unsaved", read_count())),
+		},
+		12 => format_no_std::show(buf, format_args!("Dropped presses {}", d.button_drops)),
+		_ => Ok(""),
+	};
+	result.unwrap_or_default()
+}
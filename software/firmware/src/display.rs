@@ -0,0 +1,192 @@
+//! Settings for how things are shown on the display
+
+use core::cell::Cell;
+
+use embassy_sync::{
+	blocking_mutex::{Mutex, raw::CriticalSectionRawMutex},
+	signal::Signal,
+};
+use embassy_time::{Duration, Instant};
+use embedded_graphics::prelude::Point;
+use ssd1306::prelude::DisplayRotation;
+use strum::{IntoStaticStr, VariantArray};
+
+use crate::{
+	const_default::ConstDefault,
+	record::{impl_field_for_enum, impl_record},
+};
+
+/// Size of the text in menus
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, IntoStaticStr, VariantArray, PartialEq)]
+pub enum MenuFont {
+	/// 3 rows on a 64 pixel high display, or 1 on a 32 pixel one
+	Large,
+	/// 5 rows on a 64 pixel high display, or 2 on a 32 pixel one
+	Small,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DisplayConfig {
+	pub menu_font: MenuFont,
+	/// Seconds without a button press before the menu closes itself, or 0 to leave it open
+	pub menu_timeout: u8,
+	/// Whether the title is shown above the count, rather than leaving it all to the digits
+	pub show_title: bool,
+	/// Minutes without a button press before the display dims, and then again before it turns off.
+	/// 0 keeps it on
+	pub sleep_after: u8,
+	/// Contrast of the panel as a percentage
+	pub contrast: u8,
+	/// Whether everything is turned 180°, for when the chassis is mounted upside down
+	pub upside_down: bool,
+	/// Whether the colours are swapped, so text is dark on a lit background
+	pub invert: bool,
+	/// Whether the digits roll when the count changes. Turning it off saves power spent redrawing
+	pub animate: bool,
+}
+impl DisplayConfig {
+	/// Contrast to set the panel to while it's awake, out of 255
+	pub fn panel_contrast(&self) -> u8 {
+		((self.contrast.min(100) as u32 * 255 + 50) / 100) as u8
+	}
+	pub fn rotation(&self) -> DisplayRotation {
+		if self.upside_down {
+			DisplayRotation::Rotate180
+		} else {
+			DisplayRotation::Rotate0
+		}
+	}
+}
+impl ConstDefault for DisplayConfig {
+	const DEFAULT: Self = Self {
+		menu_font: MenuFont::Large,
+		menu_timeout: 30,
+		show_title: true,
+		sleep_after: 5,
+		contrast: 40,
+		upside_down: false,
+		invert: false,
+		animate: true,
+	};
+}
+impl_field_for_enum!(MenuFont);
+impl_record!(
+	DisplayConfig,
+	version 1,
+	[menu_font, menu_timeout, show_title, sleep_after, contrast, upside_down, invert, animate]
+);
+
+/// Signalled when something shown on the count screen changes without a button being pressed
+pub static REDRAW: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Whether the display isn't responding, so the LED is the only feedback
+static HEADLESS: Mutex<CriticalSectionRawMutex, Cell<bool>> = Mutex::new(Cell::new(false));
+
+pub fn is_headless() -> bool {
+	HEADLESS.lock(|h| h.get())
+}
+
+pub fn set_headless(headless: bool) {
+	HEADLESS.lock(|h| h.set(headless))
+}
+
+pub static DISPLAY_CONFIG: Mutex<CriticalSectionRawMutex, Cell<DisplayConfig>> =
+	Mutex::new(Cell::new(DisplayConfig::DEFAULT));
+
+pub fn read_display_config() -> DisplayConfig {
+	DISPLAY_CONFIG.lock(|c| c.get())
+}
+
+pub fn write_display_config(config: DisplayConfig) {
+	DISPLAY_CONFIG.lock(|c| c.set(config))
+}
+
+/// Most pixels the main screen is moved by in each direction, so the same pixels aren't lit forever
+pub const PIXEL_SHIFT: u32 = 2;
+/// How long the main screen stays in one place
+const SHIFT_INTERVAL: Duration = Duration::from_secs(60);
+/// Positions the main screen moves through, one pixel at a time where possible
+const SHIFT_PATTERN: [(i32, i32); 9] = [
+	(0, 0),
+	(1, 0),
+	(2, 0),
+	(2, 1),
+	(1, 1),
+	(0, 1),
+	(0, 2),
+	(1, 2),
+	(2, 2),
+];
+
+/// Offset of the main screen at `now`
+pub fn pixel_shift(now: Instant) -> Point {
+	let step = now.as_ticks() / SHIFT_INTERVAL.as_ticks();
+	let (x, y) = SHIFT_PATTERN[(step % SHIFT_PATTERN.len() as u64) as usize];
+	Point::new(x, y)
+}
+
+/// When the main screen next moves after `now`
+pub fn next_shift(now: Instant) -> Instant {
+	let step = now.as_ticks() / SHIFT_INTERVAL.as_ticks();
+	Instant::from_ticks((step + 1) * SHIFT_INTERVAL.as_ticks())
+}
+
+/// How far the display has gone to sleep
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Wakefulness {
+	Awake,
+	Dim,
+	Off,
+}
+
+/// Tracks when a button was last pressed, so the display can dim and then turn off when left alone
+pub struct Sleep {
+	last_input: Instant,
+}
+impl Sleep {
+	pub fn new() -> Self {
+		Self {
+			last_input: Instant::now(),
+		}
+	}
+	/// Starts the timeout again after a button press
+	pub fn wake(&mut self) {
+		self.last_input = Instant::now();
+	}
+	/// How long until the display dims, and the same again until it turns off
+	fn timeout() -> Option<Duration> {
+		match read_display_config().sleep_after {
+			0 => None,
+			minutes => Some(Duration::from_secs(minutes as u64 * 60)),
+		}
+	}
+	/// How far the display should have gone to sleep by now
+	pub fn wakefulness(&self) -> Wakefulness {
+		let Some(timeout) = Self::timeout() else {
+			return Wakefulness::Awake;
+		};
+		let idle = Instant::now().saturating_duration_since(self.last_input);
+		if idle >= timeout + timeout {
+			Wakefulness::Off
+		} else if idle >= timeout {
+			Wakefulness::Dim
+		} else {
+			Wakefulness::Awake
+		}
+	}
+	/// When the display next goes further to sleep, if it will
+	pub fn next_change(&self) -> Option<Instant> {
+		let timeout = Self::timeout()?;
+		match self.wakefulness() {
+			Wakefulness::Awake => Some(self.last_input + timeout),
+			Wakefulness::Dim => Some(self.last_input + timeout + timeout),
+			Wakefulness::Off => None,
+		}
+	}
+}
+impl Default for Sleep {
+	fn default() -> Self {
+		Self::new()
+	}
+}
@@ -0,0 +1,43 @@
+//! Short-lived highlights shown on the LED and display, such as when the count reaches a milestone or the target
+
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use embassy_time::{Duration, Instant};
+
+use crate::count::Count;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Highlight {
+	/// The count reached a multiple of the milestone interval
+	Milestone(Count),
+	/// The count reached the target
+	Goal,
+	/// The count couldn't change because it's at the limit of its range
+	Limit,
+}
+impl Highlight {
+	/// How long the highlight is shown for
+	fn length(&self) -> Duration {
+		match self {
+			Self::Milestone(_) | Self::Goal => Duration::from_secs(3),
+			Self::Limit => Duration::from_millis(1000),
+		}
+	}
+}
+
+/// The current highlight, and when it ends
+static HIGHLIGHT: Mutex<CriticalSectionRawMutex, Cell<Option<(Highlight, Instant)>>> =
+	Mutex::new(Cell::new(None));
+
+pub fn highlight(highlight: Highlight) {
+	let end = Instant::now() + highlight.length();
+	HIGHLIGHT.lock(|h| h.set(Some((highlight, end))));
+}
+
+/// The highlight that's currently being shown (if any), and when it ends
+pub fn current_highlight() -> Option<(Highlight, Instant)> {
+	HIGHLIGHT
+		.lock(|h| h.get())
+		.filter(|(_, end)| *end > Instant::now())
+}
@@ -0,0 +1,47 @@
+//! Everything but the entry point (see main.rs). Without the `device` feature it builds for the host,
+//! where the tests run the storage tasks against a mock flash
+#![cfg_attr(not(test), no_std)]
+
+pub mod alarm;
+pub mod board;
+pub mod clock;
+pub mod config;
+pub mod const_default;
+pub mod count;
+pub mod diagnostics;
+pub mod display;
+pub mod highlight;
+pub mod maths;
+pub mod md5;
+pub mod menustate;
+#[cfg(any(test, feature = "mock-flash"))]
+pub mod mock_flash;
+pub mod odometer;
+pub mod panel;
+pub mod partitions;
+pub mod record;
+pub mod screens;
+pub mod sh1106;
+pub mod spinner;
+pub mod stats;
+pub mod storage;
+pub mod tasks;
+pub mod text_entry;
+pub mod title;
+pub mod toast;
+pub mod wear;
+
+/// Prints to the serial console on the device, and to the terminal in tests
+#[cfg(feature = "device")]
+pub(crate) use esp_println::println;
+#[cfg(all(test, not(feature = "device")))]
+pub(crate) use std::println;
+/// There's nowhere to print to when the library is built for the host outside of tests
+#[cfg(not(any(test, feature = "device")))]
+macro_rules! println {
+	($($arg:tt)*) => {{
+		let _ = format_args!($($arg)*);
+	}};
+}
+#[cfg(not(any(test, feature = "device")))]
+pub(crate) use println;
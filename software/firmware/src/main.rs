@@ -1,23 +1,14 @@
 #![no_std]
 #![no_main]
 
-use crate::config::reset;
-use crate::count::{COUNT, decrement_count, increment_count};
-use crate::menustate::{MAIN_MENU, MenuResult, State, default_index};
-use crate::tasks::handle_button::{BUTTON_STATE, ButtonEvent, handle_button};
-use crate::tasks::handle_neopixel::{RGB_CONFIG, RGB_CONFIG_UPDATED, handle_neopixel};
-use crate::tasks::handle_storage::handle_storage;
-use embassy_futures::select::Either;
-use embassy_futures::yield_now;
+use display_interface::DisplayError;
+use embassy_futures::select::{Either3, select3};
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::mutex::Mutex;
-use embedded_graphics::Drawable;
-use embedded_graphics::mono_font::iso_8859_9::FONT_10X20;
+use embassy_time::{Duration, Instant, Timer};
 use embedded_graphics::{
-	mono_font::MonoTextStyleBuilder,
-	pixelcolor::BinaryColor,
-	prelude::Point,
-	text::{Baseline, Text},
+	prelude::{Dimensions, DrawTargetExt, Size},
+	primitives::Rectangle,
 };
 use esp_backtrace as _;
 use esp_hal::{
@@ -26,21 +17,63 @@ use esp_hal::{
 	time::Rate,
 	timer::timg::TimerGroup,
 };
-
-use ssd1306::{
-	I2CDisplayInterface, Ssd1306Async, mode::DisplayConfigAsync, prelude::DisplayRotation,
-	size::DisplaySize128x64,
+use esp_println::println;
+use item_death_toll_firmware::alarm::{acknowledge_alarm, alarm_active};
+use item_death_toll_firmware::board;
+use item_death_toll_firmware::count::{COUNT, advance_count, read_counter_config, retreat_count};
+use item_death_toll_firmware::diagnostics;
+use item_death_toll_firmware::display::{
+	PIXEL_SHIFT, REDRAW, Sleep, Wakefulness, next_shift, pixel_shift, read_display_config,
+	set_headless,
+};
+use item_death_toll_firmware::highlight::current_highlight;
+use item_death_toll_firmware::menustate::{MAIN_MENU, Navigator, State};
+use item_death_toll_firmware::odometer::Odometer;
+use item_death_toll_firmware::panel::Panel;
+use item_death_toll_firmware::screens::{
+	MARQUEE_STEP, render_count, render_diagnostics, render_graph, render_menu, render_splash,
+	render_stats, render_toast,
 };
-pub mod config;
-pub mod const_default;
-pub mod count;
-pub mod maths;
-pub mod menustate;
-pub mod storage;
-pub mod tasks;
+use item_death_toll_firmware::tasks::handle_alarm::handle_alarm;
+use item_death_toll_firmware::tasks::handle_button::{BUTTON_STATE, ButtonEvent, handle_button};
+use item_death_toll_firmware::tasks::handle_console::handle_console;
+use item_death_toll_firmware::tasks::handle_neopixel::handle_neopixel;
+use item_death_toll_firmware::tasks::handle_storage::handle_storage;
+use item_death_toll_firmware::title::read_title;
+use item_death_toll_firmware::toast::Toaster;
+use ssd1306::I2CDisplayInterface;
 
 pub static MENU_STATE: Mutex<CriticalSectionRawMutex, State> = Mutex::new(State::DeathToll);
 
+/// How long the splash screen is shown for at boot
+const SPLASH_TIME: Duration = Duration::from_millis(1500);
+
+/// How often to try setting up the display again while it isn't responding
+const DISPLAY_RETRY: Duration = Duration::from_secs(5);
+
+/// Waits for a button press, or until `redraw_at` so the screen can be redrawn (returning None).
+/// A press that wakes the display is used up doing so, so it doesn't also count,
+/// unless there's no display to wake
+async fn next_event(
+	sleep: &mut Sleep,
+	redraw_at: Option<Instant>,
+	headless: bool,
+) -> Option<ButtonEvent> {
+	let redraw_at = redraw_at
+		.into_iter()
+		.chain(sleep.next_change())
+		.min()
+		.unwrap_or(Instant::MAX);
+	match select3(BUTTON_STATE.wait(), Timer::at(redraw_at), REDRAW.wait()).await {
+		Either3::First(event) => {
+			let asleep = sleep.wakefulness() != Wakefulness::Awake && !headless;
+			sleep.wake();
+			(!asleep).then_some(event)
+		}
+		Either3::Second(_) | Either3::Third(_) => None,
+	}
+}
+
 #[esp_hal_embassy::main]
 async fn main(spawner: embassy_executor::Spawner) {
 	esp_println::logger::init_logger_from_env();
@@ -53,6 +86,11 @@ async fn main(spawner: embassy_executor::Spawner) {
 	spawner
 		.spawn(handle_button(peripherals.GPIO2, peripherals.GPIO3))
 		.unwrap();
+	// Active buzzer for the countdown alarm
+	spawner.spawn(handle_alarm(peripherals.GPIO4)).unwrap();
+	spawner
+		.spawn(handle_console(peripherals.USB_DEVICE))
+		.unwrap();
 	let frequency = Rate::from_mhz(80);
 	let rmt = Rmt::new(peripherals.RMT, frequency)
 		.expect("Failed to initialize RMT0")
@@ -70,182 +108,224 @@ async fn main(spawner: embassy_executor::Spawner) {
 		.with_sda(peripherals.GPIO6)
 		.into_async();
 	let interface = I2CDisplayInterface::new(i2c);
-	let mut display = Ssd1306Async::new(interface, DisplaySize128x64, DisplayRotation::Rotate0)
-		.into_buffered_graphics_mode();
-	if let Err(e) = display.init().await {
-		esp_println::println!("Error: {:?}", e);
-		esp_println::println!("Display couldn't be initialised");
-		loop {
-			yield_now().await;
+	let mut display = board::display(interface);
+	// Without a display the counter still works, with the LED as the only feedback
+	let mut retry_display = match display.init().await {
+		Ok(()) => {
+			let mut buf = [0u8; 32];
+			// Shown while the stored settings and count are loaded
+			if render_splash(&mut display, &mut buf).is_ok() && display.flush().await.is_ok() {
+				Timer::after(SPLASH_TIME).await;
+			}
+			None
 		}
-	}
-	let text_style = MonoTextStyleBuilder::new()
-		.font(&FONT_10X20)
-		.text_color(BinaryColor::On)
-		.build();
+		Err(e) => {
+			println!("Display couldn't be initialised: {:?}", e);
+			Some(Instant::now() + DISPLAY_RETRY)
+		}
+	};
 
-	let mut buf = [0u8; 30];
-	let mut menu_index: usize = 0;
-	let send = RGB_CONFIG_UPDATED.sender();
+	let mut buf = [0u8; 64];
+	let mut navigator = Navigator::new(&MAIN_MENU);
+	let mut inverted = false;
+	let mut upside_down = false;
+	let mut sleep = Sleep::new();
+	let mut odometer = Odometer::new();
+	let mut toaster = Toaster::new();
+	// What the panel was last set to, so it's only sent again when it changes
+	let mut applied: Option<(Wakefulness, u8)> = None;
 	loop {
+		if let Some(retry_at) = retry_display
+			&& retry_at <= Instant::now()
+		{
+			retry_display = match display.init().await {
+				Ok(()) => {
+					println!("Display reconnected");
+					// Setting it up again resets the panel's settings, apart from the rotation
+					applied = None;
+					inverted = false;
+					None
+				}
+				Err(_) => Some(Instant::now() + DISPLAY_RETRY),
+			};
+		}
+		let headless = retry_display.is_some();
+		set_headless(headless);
 		// Clone the value and drop the lock immediately (so it can be modified by another task)
 		let value = { MENU_STATE.lock().await.clone() };
+		let alarm = alarm_active();
 
-		match value {
-			State::DeathToll => {
-				let value = COUNT.try_get().unwrap_or(0);
+		// Draws the screen, returning when it next needs drawing without a button press
+		let drawn: Result<Option<Instant>, DisplayError> = if headless {
+			Ok(None)
+		} else {
+			async {
+				// Settings are applied as soon as they change, so the effect can be seen from the menu
+				let config = read_display_config();
+				let wakefulness = sleep.wakefulness();
+				if applied != Some((wakefulness, config.panel_contrast())) {
+					match wakefulness {
+						Wakefulness::Awake => {
+							display.set_contrast(config.panel_contrast()).await?;
+							display.set_display_on(true).await?;
+						}
+						Wakefulness::Dim => display.set_contrast(0).await?,
+						Wakefulness::Off => display.set_display_on(false).await?,
+					}
+					applied = Some((wakefulness, config.panel_contrast()));
+				}
+				if config.upside_down != upside_down {
+					display.set_rotation(config.rotation()).await?;
+					upside_down = config.upside_down;
+				}
+				// Invert the display while the alarm is raised, which swaps it back if it's already inverted
+				let invert = config.invert != alarm;
+				if invert != inverted {
+					display.set_invert(invert).await?;
+					inverted = invert;
+				}
+				// Nothing can be seen, so there's no point drawing
+				if wakefulness == Wakefulness::Off {
+					return Ok(None);
+				}
 				display.clear_buffer();
-				Text::with_baseline("Death Toll", Point::zero(), text_style, Baseline::Top)
-					.draw(&mut display)
-					.unwrap();
-				Text::with_baseline(
-					format_no_std::show(&mut buf, format_args!("{value}")).unwrap(),
-					Point::new(0, 20),
-					text_style,
-					Baseline::Top,
-				)
-				.draw(&mut display)
-				.unwrap();
+				let redraw_at = match value {
+					State::DeathToll => {
+						let title = read_title();
+						// Drawn slightly smaller than the display, and moved around within it to avoid burn-in
+						let now = Instant::now();
+						let size = display.bounding_box().size;
+						let area = Rectangle::new(
+							pixel_shift(now),
+							Size::new(size.width - PIXEL_SHIFT, size.height - PIXEL_SHIFT),
+						);
+						let count = COUNT.try_get();
+						// Not rolled from before the stored count is loaded
+						if let Some(count) = count {
+							odometer.update(count, now, config.animate);
+						}
+						render_count(
+							&mut display.cropped(&area),
+							config.show_title.then_some(title.as_str()),
+							count.unwrap_or(0),
+							odometer.frame(now),
+							&read_counter_config(),
+							&mut buf,
+						)?;
+						// Redraw when the highlight finishes, the screen moves or for the next frame
+						[
+							Some(next_shift(now)),
+							current_highlight().map(|(_, end)| end),
+							odometer.next_frame(now),
+						]
+						.into_iter()
+						.flatten()
+						.min()
+					}
+					State::Stats(period) => {
+						render_stats(&mut display, period, &mut buf)?;
+						None
+					}
+					State::Graph(range) => {
+						render_graph(&mut display, range, &mut buf)?;
+						None
+					}
+					State::Diagnostics(first) => {
+						render_diagnostics(&mut display, first, &mut buf)?;
+						// Keep the uptime ticking
+						Some(Instant::now() + Duration::from_secs(1))
+					}
+					State::Menu => {
+						let animating = render_menu(&mut display, &navigator, &mut buf)?;
+						// Redraw as things move
+						animating.then(|| Instant::now() + MARQUEE_STEP)
+					}
+				};
+				// Messages are shown over whichever screen is active
+				if let Some(message) = toaster.current(Instant::now()) {
+					render_toast(&mut display, message)?;
+				}
+				display.flush().await?;
+				Ok(redraw_at.into_iter().chain(toaster.ends_at()).min())
+			}
+			.await
+		};
+		let redraw_at = match drawn {
+			Ok(redraw_at) => redraw_at,
+			// Most likely a loose wire, so try setting the display up again straight away
+			Err(e) => {
+				println!("Display stopped responding: {:?}", e);
+				retry_display = Some(Instant::now());
+				continue;
+			}
+		};
+		// Also wake up when the confirmation or menu times out, or to try the display again
+		let menu_timeout = matches!(value, State::Menu)
+			.then(|| navigator.wake_at())
+			.flatten();
+		let redraw_at = redraw_at
+			.into_iter()
+			.chain(menu_timeout)
+			.chain(retry_display)
+			.min();
+		let Some(event) = next_event(&mut sleep, redraw_at, headless).await else {
+			if matches!(value, State::Menu)
+				&& let Some(state) = navigator.time_out().await
+			{
+				*MENU_STATE.lock().await = state;
+			}
+			continue;
+		};
 
-				display.flush().await.unwrap();
-				match BUTTON_STATE.wait().await {
+		match value {
+			State::DeathToll => {
+				// Any button press acknowledges the alarm without changing the count
+				if alarm {
+					acknowledge_alarm();
+					continue;
+				}
+				match event {
 					ButtonEvent::Press => {
-						increment_count();
+						advance_count();
 					}
 					ButtonEvent::HoldHalfSecond => {
-						decrement_count();
+						retreat_count();
 					}
 					ButtonEvent::HoldFullSecond => {
-						*MENU_STATE.lock().await = State::Menu(&MAIN_MENU);
+						navigator = Navigator::new(&MAIN_MENU);
+						*MENU_STATE.lock().await = State::Menu;
 					}
 				}
 			}
-			State::Menu(menu) => {
-				let items = &menu.items;
-				match items {
-					Either::First(x) => {
-						render_list(x, menu_index, &mut display, &mut buf, text_style).await;
-						match BUTTON_STATE.wait().await {
-							ButtonEvent::Press => {
-								menu_index += 1;
-								if menu_index >= x.len() {
-									menu_index = 0
-								}
-							}
-							ButtonEvent::HoldHalfSecond => {
-								let new_menu = &x[menu_index];
-								let new_index = default_index(new_menu).await;
-								*MENU_STATE.lock().await = State::Menu(new_menu);
-								menu_index = new_index;
-							}
-							ButtonEvent::HoldFullSecond => {
-								*MENU_STATE.lock().await = State::DeathToll;
-							}
-						}
-					}
-					Either::Second(x) => {
-						render_list(x, menu_index, &mut display, &mut buf, text_style).await;
-						match BUTTON_STATE.wait().await {
-							ButtonEvent::Press => {
-								menu_index += 1;
-								if menu_index >= x.len() {
-									menu_index = 0
-								}
-							}
-							ButtonEvent::HoldHalfSecond => {
-								let result = x[menu_index].clone();
-								let mut rgb_config = RGB_CONFIG.lock().await;
-								match result {
-									MenuResult::RgbMode(mode) => {
-										rgb_config.set_mode(mode);
-									}
-									MenuResult::RgbBrightness(brightness) => {
-										rgb_config.set_brightness(brightness);
-									}
-									MenuResult::RgbRate(rate) => {
-										rgb_config.set_rate(rate);
-									}
-									MenuResult::ResetOptions(o) => {
-										drop(rgb_config);
-										reset(o).await;
-									}
-								}
-								send.send(0);
-							}
-							ButtonEvent::HoldFullSecond => {
-								menu_index = 0;
-								*MENU_STATE.lock().await = State::DeathToll;
-							}
-						}
-					}
+			State::Stats(period) => match event {
+				ButtonEvent::Press => {
+					*MENU_STATE.lock().await = State::Stats(period.next());
+				}
+				ButtonEvent::HoldHalfSecond | ButtonEvent::HoldFullSecond => {
+					*MENU_STATE.lock().await = State::DeathToll;
+				}
+			},
+			State::Graph(range) => match event {
+				ButtonEvent::Press => {
+					*MENU_STATE.lock().await = State::Graph(range.next());
+				}
+				ButtonEvent::HoldHalfSecond | ButtonEvent::HoldFullSecond => {
+					*MENU_STATE.lock().await = State::DeathToll;
+				}
+			},
+			State::Diagnostics(first) => match event {
+				ButtonEvent::Press => {
+					*MENU_STATE.lock().await = State::Diagnostics((first + 1) % diagnostics::LINES);
+				}
+				ButtonEvent::HoldHalfSecond | ButtonEvent::HoldFullSecond => {
+					*MENU_STATE.lock().await = State::DeathToll;
+				}
+			},
+			State::Menu => {
+				if let Some(state) = navigator.handle(event).await {
+					*MENU_STATE.lock().await = state;
 				}
 			}
 		}
 	}
 }
-async fn render_list<'a, T: Clone + Into<&'a str>>(
-	items: &[T],
-	index: usize,
-	display: &mut Ssd1306Async<
-		ssd1306::prelude::I2CInterface<i2c::master::I2c<'_, esp_hal::Async>>,
-		DisplaySize128x64,
-		ssd1306::mode::BufferedGraphicsModeAsync<DisplaySize128x64>,
-	>,
-	text_buf: &mut [u8],
-	text_style: embedded_graphics::mono_font::MonoTextStyle<'_, BinaryColor>,
-) {
-	if items.is_empty() {
-		return;
-	}
-	let i: i8 = index as i8;
-	// Current value should be rendered in the middle with a <
-	let previous_value = items[{
-		let index = i - 1;
-		if index < 0 {
-			items.len() as i8 + index
-		} else {
-			index
-		}
-	} as usize]
-		.clone();
-	let current_value = items[i as usize].clone();
-	let next_value = items[{
-		let index = i + 1;
-		if index >= items.len() as i8 {
-			index - items.len() as i8
-		} else {
-			index
-		}
-	} as usize]
-		.clone();
-	display.clear_buffer();
-	Text::with_baseline(
-		previous_value.into(),
-		Point::zero(),
-		text_style,
-		Baseline::Top,
-	)
-	.draw(display)
-	.unwrap();
-	Text::with_baseline(
-		format_no_std::show(
-			text_buf,
-			format_args!("{} <", <T as Into<&str>>::into(current_value)),
-		)
-		.unwrap(),
-		Point::new(0, 20),
-		text_style,
-		Baseline::Top,
-	)
-	.draw(display)
-	.unwrap();
-	Text::with_baseline(
-		next_value.into(),
-		Point::new(0, 40),
-		text_style,
-		Baseline::Top,
-	)
-	.draw(display)
-	.unwrap();
-	display.flush().await.unwrap();
-}
@@ -0,0 +1,141 @@
+//! MD5 (RFC 1321), which the partition table is checked with. It only catches damage, so it mustn't
+//! be relied on for anything that needs to be secure
+
+/// Bits each step's result is rotated by
+const SHIFTS: [u32; 64] = [
+	7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, //
+	5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20, //
+	4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, //
+	6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+];
+/// Added in each step, from the sines of 1 to 64 (the integer part of `abs(sin(i + 1)) * 2^32`)
+const SINES: [u32; 64] = [
+	0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501,
+	0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821,
+	0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8,
+	0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed, 0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a,
+	0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70,
+	0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665,
+	0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+	0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391,
+];
+
+/// Digest of bytes given a few at a time, so the whole input doesn't need to be in memory
+#[derive(Clone)]
+pub struct Md5 {
+	state: [u32; 4],
+	/// Bytes of the block being filled
+	block: [u8; 64],
+	/// Bytes given so far
+	len: u64,
+}
+impl Default for Md5 {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+impl Md5 {
+	pub const fn new() -> Self {
+		Self {
+			state: [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476],
+			block: [0; 64],
+			len: 0,
+		}
+	}
+	pub fn update(&mut self, mut bytes: &[u8]) {
+		while !bytes.is_empty() {
+			let filled = (self.len % 64) as usize;
+			let taken = (64 - filled).min(bytes.len());
+			self.block[filled..filled + taken].copy_from_slice(&bytes[..taken]);
+			self.len += taken as u64;
+			bytes = &bytes[taken..];
+			if filled + taken == 64 {
+				self.compress();
+			}
+		}
+	}
+	/// The digest of everything given
+	pub fn finish(mut self) -> [u8; 16] {
+		let bits = self.len.wrapping_mul(8);
+		// Padded with a single set bit and then zeroes, up to the length at the end of the last block
+		self.update(&[0x80]);
+		while self.len % 64 != 56 {
+			self.update(&[0]);
+		}
+		self.update(&bits.to_le_bytes());
+		let mut digest = [0; 16];
+		for (bytes, word) in digest.chunks_exact_mut(4).zip(self.state) {
+			bytes.copy_from_slice(&word.to_le_bytes());
+		}
+		digest
+	}
+	/// Mixes a full block into the state
+	fn compress(&mut self) {
+		let mut words = [0u32; 16];
+		for (word, bytes) in words.iter_mut().zip(self.block.chunks_exact(4)) {
+			*word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+		}
+		let [mut a, mut b, mut c, mut d] = self.state;
+		for i in 0..64 {
+			let (mixed, word) = match i / 16 {
+				0 => ((b & c) | (!b & d), i),
+				1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+				2 => (b ^ c ^ d, (3 * i + 5) % 16),
+				_ => (c ^ (b | !d), (7 * i) % 16),
+			};
+			let mixed = mixed
+				.wrapping_add(a)
+				.wrapping_add(SINES[i])
+				.wrapping_add(words[word]);
+			a = d;
+			d = c;
+			c = b;
+			b = b.wrapping_add(mixed.rotate_left(SHIFTS[i]));
+		}
+		for (state, x) in self.state.iter_mut().zip([a, b, c, d]) {
+			*state = state.wrapping_add(x);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn digest(bytes: &[u8]) -> [u8; 16] {
+		let mut md5 = Md5::new();
+		md5.update(bytes);
+		md5.finish()
+	}
+
+	fn hex(digest: [u8; 16]) -> String {
+		digest.iter().map(|b| format!("{b:02x}")).collect()
+	}
+
+	#[test]
+	fn matches_rfc_1321() {
+		assert_eq!(hex(digest(b"")), "d41d8cd98f00b204e9800998ecf8427e");
+		assert_eq!(hex(digest(b"abc")), "900150983cd24fb0d6963f7d28e17f72");
+		assert_eq!(
+			hex(digest(b"abcdefghijklmnopqrstuvwxyz")),
+			"c3fcd3d76192e4007dfb496cca67e13b"
+		);
+		// Longer than a block
+		assert_eq!(
+			hex(digest(
+				b"12345678901234567890123456789012345678901234567890123456789012345678901234567890"
+			)),
+			"57edf4a22be3c955ac49da2e2107b67a"
+		);
+	}
+
+	#[test]
+	fn same_digest_in_pieces() {
+		let bytes = [0x5Au8; 200];
+		let mut md5 = Md5::new();
+		for chunk in bytes.chunks(32) {
+			md5.update(chunk);
+		}
+		assert_eq!(md5.finish(), digest(&bytes));
+	}
+}
@@ -1,147 +1,807 @@
 use core::mem::MaybeUninit;
 
 use crate::{
-	const_default::ConstDefault,
-	tasks::handle_neopixel::{RGB_CONFIG, RgbMode},
+	config::reset,
+	count::{
+		CounterConfig, Direction, OverflowPolicy, Signedness, fit_count_to_range, read_count,
+		read_counter_config, write_count, write_counter_config,
+	},
+	display::{DisplayConfig, MenuFont, read_display_config, write_display_config},
+	spinner::Spinner,
+	stats::{
+		DayStart, GraphRange, StatsConfig, StatsPeriod, WeekStart, read_stats_config,
+		write_stats_config,
+	},
+	tasks::{
+		handle_button::ButtonEvent,
+		handle_neopixel::{RGB_CONFIG, RGB_RATES, RgbMode},
+		handle_storage::CONFIG_UPDATED,
+	},
+	text_entry::TextEntry,
+	title::{Title, read_title, write_title},
+	wear::{SaveConfig, SavePolicy, read_save_config, write_save_config},
 };
-use embassy_futures::select::Either;
-use strum::{EnumDiscriminants, IntoStaticStr, VariantArray};
+use embassy_time::{Duration, Instant};
+use strum::{IntoStaticStr, VariantArray};
 
 #[derive(Clone, Debug)]
-pub enum State<'a> {
+pub enum State {
 	/// Not in a menu. Display the death toll
 	DeathToll,
-	Menu(&'a Menu<'a>),
+	/// Navigating the menu tree (see `Navigator`)
+	Menu,
+	/// Display the tally for a period
+	Stats(StatsPeriod),
+	/// Display a graph of the count history
+	Graph(GraphRange),
+	/// Display information about the health of the device, scrolled down to a line
+	Diagnostics(usize),
 }
 
-pub trait Renderable {
-	/// Renders the menu with the item at the given index selected
-	fn render(&self, index: usize);
-}
-type MenuItems<'a> = Either<&'a [Menu<'a>], &'a [MenuResult]>;
 #[derive(Clone, Debug)]
 pub struct Menu<'a> {
 	pub name: &'a str,
-	pub items: MenuItems<'a>,
-}
-impl<'a> From<Menu<'a>> for &'a str {
-	fn from(val: Menu<'a>) -> Self {
-		val.name
-	}
+	pub items: &'a [MenuItem<'a>],
 }
 impl<'a> Menu<'a> {
-	pub const fn new(name: &'a str, items: MenuItems<'a>) -> Self {
+	pub const fn new(name: &'a str, items: &'a [MenuItem<'a>]) -> Self {
 		Self { name, items }
 	}
 }
 
+/// A single entry in a menu
+#[derive(Clone, Debug)]
+pub enum MenuItem<'a> {
+	/// Opens a nested menu
+	Submenu(Menu<'a>),
+	/// Applies a setting or opens a screen
+	Action(MenuResult),
+	/// Switches a setting on or off
+	Toggle(Toggle),
+	/// Edits a number with a spinner
+	Number(NumberSetting),
+	Text(TextSetting),
+	/// Returns to the parent menu (or leaves the menu from the top level)
+	Back,
+}
+impl<'a> MenuItem<'a> {
+	/// Text shown for the item. `buf` is used for labels that include the current state.
+	/// `value` is the number's current value, if the item is a number (see `Navigator::value`)
+	pub fn label<'b>(&self, value: u32, buf: &'b mut [u8]) -> &'b str
+	where
+		'a: 'b,
+	{
+		match self {
+			Self::Submenu(menu) => menu.name,
+			Self::Action(result) => result.clone().into(),
+			Self::Toggle(toggle) => {
+				let state = if toggle.get() { "On" } else { "Off" };
+				let name: &str = (*toggle).into();
+				format_no_std::show(buf, format_args!("{name}: {state}")).unwrap_or(name)
+			}
+			Self::Number(setting) => {
+				let name: &str = (*setting).into();
+				let (label_buf, value_buf) = buf.split_at_mut(buf.len() / 2);
+				let value = setting.format(value, value_buf);
+				format_no_std::show(label_buf, format_args!("{name}: {value}")).unwrap_or(name)
+			}
+			Self::Text(setting) => (*setting).into(),
+			Self::Back => "Back",
+		}
+	}
+	/// Whether the item is the option that's currently applied.
+	/// Toggles and numbers show their state in their label instead
+	pub async fn is_active(&self) -> bool {
+		match self {
+			Self::Action(result) => result.is_active().await,
+			_ => false,
+		}
+	}
+}
+
+/// Boolean settings that can be switched from a menu
+#[derive(Debug, Clone, Copy, IntoStaticStr, PartialEq)]
+pub enum Toggle {
+	/// Sound the buzzer as well as flashing when the alarm is raised
+	Buzzer,
+	/// Show the title above the count
+	Title,
+	/// Turn the display 180°
+	#[strum(serialize = "Upside Down")]
+	UpsideDown,
+	/// Swap the display's colours
+	Invert,
+	/// Roll the digits when the count changes
+	Animate,
+}
+impl Toggle {
+	pub fn get(self) -> bool {
+		match self {
+			Self::Buzzer => read_counter_config().buzzer,
+			Self::Title => read_display_config().show_title,
+			Self::UpsideDown => read_display_config().upside_down,
+			Self::Invert => read_display_config().invert,
+			Self::Animate => read_display_config().animate,
+		}
+	}
+	pub fn set(self, on: bool) {
+		match self {
+			Self::Buzzer => write_counter_config(CounterConfig {
+				buzzer: on,
+				..read_counter_config()
+			}),
+			Self::Title => write_display_config(DisplayConfig {
+				show_title: on,
+				..read_display_config()
+			}),
+			Self::UpsideDown => write_display_config(DisplayConfig {
+				upside_down: on,
+				..read_display_config()
+			}),
+			Self::Invert => write_display_config(DisplayConfig {
+				invert: on,
+				..read_display_config()
+			}),
+			Self::Animate => write_display_config(DisplayConfig {
+				animate: on,
+				..read_display_config()
+			}),
+		}
+	}
+}
+
+/// Integer settings that can be edited with a spinner
+#[derive(Debug, Clone, Copy, IntoStaticStr, PartialEq)]
+pub enum NumberSetting {
+	/// Count to aim for
+	Target,
+	/// Interval between celebrations
+	Milestones,
+	/// Value that a countdown starts from
+	#[strum(serialize = "Start From")]
+	StartFrom,
+	/// Amount the count changes by with each press
+	Step,
+	/// Brightness of the LED as a percentage
+	Brightness,
+	/// Speed of the RGB effects
+	#[strum(serialize = "RGB Rate")]
+	RgbRate,
+	/// Seconds before the menu closes itself
+	#[strum(serialize = "Menu Timeout")]
+	MenuTimeout,
+	/// Minutes before the display dims, then turns off
+	#[strum(serialize = "Sleep After")]
+	SleepAfter,
+	/// Contrast of the display as a percentage
+	Contrast,
+}
+impl NumberSetting {
+	/// Spinner for editing the setting, starting at its current value
+	pub async fn spinner(self) -> Spinner {
+		let value = self.get().await;
+		let (min, max, step) = match self {
+			// Any count can be aimed for, which is too many to step through
+			Self::Target | Self::Milestones => return Spinner::by_digits(value, 0, u32::MAX),
+			// A countdown from 0 would be over before it started
+			Self::StartFrom => return Spinner::by_digits(value, 1, u32::MAX),
+			Self::Step => (1, 100, 1),
+			Self::Brightness => (5, 100, 5),
+			// The effects divide by the rate, so it can't be 0
+			Self::RgbRate => (*RGB_RATES.start(), *RGB_RATES.end(), 1),
+			// Stored as a u8
+			Self::MenuTimeout => (0, 240, 10),
+			Self::SleepAfter => (0, 120, 1),
+			Self::Contrast => (0, 100, 10),
+		};
+		Spinner::new(value, min, max, step)
+	}
+	pub async fn get(self) -> u32 {
+		let counter_config = read_counter_config();
+		match self {
+			Self::Target => counter_config.target,
+			Self::Milestones => counter_config.milestone,
+			Self::StartFrom => counter_config.start,
+			Self::Step => counter_config.step,
+			// Stored out of 255
+			Self::Brightness => (RGB_CONFIG.lock().await.rgb_brightness as u32 * 100 + 127) / 255,
+			Self::RgbRate => RGB_CONFIG.lock().await.rgb_rate_modifier as u32,
+			Self::MenuTimeout => read_display_config().menu_timeout as u32,
+			Self::SleepAfter => read_display_config().sleep_after as u32,
+			Self::Contrast => read_display_config().contrast as u32,
+		}
+	}
+	/// Applies the value straight away, so the effect can be seen while editing
+	pub async fn set(self, value: u32) {
+		let counter_config = read_counter_config();
+		match self {
+			Self::Target => write_counter_config(CounterConfig {
+				target: value,
+				..counter_config
+			}),
+			Self::Milestones => write_counter_config(CounterConfig {
+				milestone: value,
+				..counter_config
+			}),
+			// The countdown only restarts from it once that's been confirmed (see `Navigator::handle_event`)
+			Self::StartFrom => write_counter_config(CounterConfig {
+				start: value,
+				..counter_config
+			}),
+			Self::Step => write_counter_config(CounterConfig {
+				step: value,
+				..counter_config
+			}),
+			Self::Brightness => RGB_CONFIG
+				.lock()
+				.await
+				.set_brightness(((value * 255 + 50) / 100) as u8),
+			Self::RgbRate => RGB_CONFIG.lock().await.set_rate(value as u8),
+			Self::MenuTimeout => write_display_config(DisplayConfig {
+				menu_timeout: value as u8,
+				..read_display_config()
+			}),
+			Self::SleepAfter => write_display_config(DisplayConfig {
+				sleep_after: value as u8,
+				..read_display_config()
+			}),
+			Self::Contrast => write_display_config(DisplayConfig {
+				contrast: value as u8,
+				..read_display_config()
+			}),
+		}
+	}
+	/// Text shown for `value`
+	pub fn format(self, value: u32, buf: &mut [u8]) -> &str {
+		match self {
+			Self::Target | Self::Milestones | Self::MenuTimeout | Self::SleepAfter
+				if value == 0 =>
+			{
+				"Off"
+			}
+			Self::MenuTimeout => format_no_std::show(buf, format_args!("{value}s")).unwrap_or(""),
+			Self::SleepAfter => format_no_std::show(buf, format_args!("{value}m")).unwrap_or(""),
+			Self::Brightness | Self::Contrast => {
+				format_no_std::show(buf, format_args!("{value}%")).unwrap_or("")
+			}
+			_ => format_no_std::show(buf, format_args!("{value}")).unwrap_or(""),
+		}
+	}
+}
+
+/// Text settings that can be typed one character at a time
+#[derive(Debug, Clone, Copy, IntoStaticStr, PartialEq)]
+pub enum TextSetting {
+	/// Shown above the count
+	Title,
+}
+impl TextSetting {
+	pub fn get(self) -> Title {
+		match self {
+			Self::Title => read_title(),
+		}
+	}
+	pub fn set(self, text: &str) {
+		match self {
+			Self::Title => write_title(Title::new(text)),
+		}
+	}
+}
+
+/// Deepest level of nesting in the menu tree
+const MAX_DEPTH: usize = 4;
+/// How long a confirmation waits before cancelling itself
+const CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long before the menu closes itself that a countdown is shown
+pub const TIMEOUT_WARNING: Duration = Duration::from_secs(5);
+
+/// An action waiting to be confirmed, because it can't be undone
+#[derive(Clone, Debug)]
+pub struct Confirmation {
+	pub result: MenuResult,
+	/// When the confirmation is cancelled if it hasn't been answered
+	pub deadline: Instant,
+}
+
+/// Tracks the path through the menu tree, and the selected item at each level
+pub struct Navigator {
+	stack: [(&'static Menu<'static>, usize); MAX_DEPTH],
+	depth: usize,
+	/// The setting being edited, if any
+	editing: Option<(NumberSetting, Spinner)>,
+	/// Value of the setting being edited from before it was opened, so it can be put back
+	editing_from: u32,
+	/// The text setting being typed, if any. It's only applied once finished
+	typing: Option<(TextSetting, TextEntry)>,
+	/// Which items in the current menu are active, one bit per item
+	active: u32,
+	/// Values of the numbers in the current menu, by index
+	values: [u32; u32::BITS as usize],
+	/// The action waiting to be confirmed, if any
+	confirming: Option<Confirmation>,
+	/// When the selected item was selected, so that long labels scroll from the start
+	selected_at: Instant,
+	/// When a button was last pressed, so the menu can close itself when left alone
+	last_input: Instant,
+}
+impl Navigator {
+	pub fn new(root: &'static Menu<'static>) -> Self {
+		Self {
+			stack: [(root, 0); MAX_DEPTH],
+			depth: 0,
+			editing: None,
+			editing_from: 0,
+			typing: None,
+			active: 0,
+			values: [0; u32::BITS as usize],
+			confirming: None,
+			selected_at: Instant::now(),
+			last_input: Instant::now(),
+		}
+	}
+	/// The action waiting to be confirmed, if there is one
+	pub fn confirming(&self) -> Option<&Confirmation> {
+		self.confirming.as_ref()
+	}
+	/// When the menu closes itself if no buttons are pressed, unless it's set to stay open
+	pub fn timeout(&self) -> Option<Instant> {
+		match read_display_config().menu_timeout {
+			0 => None,
+			seconds => Some(self.last_input + Duration::from_secs(seconds.into())),
+		}
+	}
+	/// How long is left before the menu closes itself, if it's close enough to warn about
+	pub fn time_left(&self) -> Option<Duration> {
+		self.timeout()
+			.map(|timeout| timeout.saturating_duration_since(Instant::now()))
+			.filter(|&left| left <= TIMEOUT_WARNING)
+	}
+	/// When something changes without a button being pressed: the confirmation being cancelled,
+	/// the countdown appearing or the menu closing
+	pub fn wake_at(&self) -> Option<Instant> {
+		let now = Instant::now();
+		let timeout = self.timeout().map(|timeout| {
+			let warning = timeout - TIMEOUT_WARNING;
+			if warning > now { warning } else { timeout }
+		});
+		let deadline = self.confirming.as_ref().map(|c| c.deadline);
+		timeout.into_iter().chain(deadline).min()
+	}
+	/// Cancels the confirmation if its deadline has passed, and closes the menu if it's been left alone
+	/// for too long, putting back any setting that's being edited and dropping any text being typed.
+	/// Returns the state to switch to, if the menu should be left
+	pub async fn time_out(&mut self) -> Option<State> {
+		let now = Instant::now();
+		if self.confirming.as_ref().is_some_and(|c| c.deadline <= now) {
+			self.confirming = None;
+		}
+		if self.timeout().is_none_or(|timeout| timeout > now) {
+			return None;
+		}
+		self.confirming = None;
+		self.typing = None;
+		if let Some((setting, _)) = self.editing.take() {
+			setting.set(self.editing_from).await;
+		}
+		Some(State::DeathToll)
+	}
+	/// The setting being edited and its spinner, if there is one open
+	pub fn editing(&self) -> Option<(NumberSetting, Spinner)> {
+		self.editing
+	}
+	/// The text setting being typed and what's been typed so far, if there is one open
+	pub fn typing(&self) -> Option<&(TextSetting, TextEntry)> {
+		self.typing.as_ref()
+	}
+	/// Responds to a button event. Returns the state to switch to, if the menu should be left
+	pub async fn handle(&mut self, event: ButtonEvent) -> Option<State> {
+		self.last_input = Instant::now();
+		let selection = (self.depth, self.index());
+		let state = self.handle_event(event).await;
+		if (self.depth, self.index()) != selection {
+			self.selected_at = Instant::now();
+		}
+		self.refresh_active().await;
+		state
+	}
+	/// How long the selected item has been selected for
+	pub fn selected_for(&self) -> Duration {
+		Instant::now().saturating_duration_since(self.selected_at)
+	}
+	async fn handle_event(&mut self, event: ButtonEvent) -> Option<State> {
+		if let Some(confirmation) = self.confirming.take() {
+			// Anything other than a deliberate hold cancels
+			return match event {
+				ButtonEvent::HoldHalfSecond => Self::apply(confirmation.result).await,
+				ButtonEvent::Press | ButtonEvent::HoldFullSecond => None,
+			};
+		}
+		if let Some((setting, entry)) = &mut self.typing {
+			match event {
+				ButtonEvent::Press => entry.cycle(),
+				ButtonEvent::HoldHalfSecond => entry.advance(),
+				ButtonEvent::HoldFullSecond => {
+					setting.set(entry.text());
+					self.typing = None;
+					CONFIG_UPDATED.signal(());
+				}
+			}
+			return None;
+		}
+		if let Some((setting, spinner)) = &mut self.editing {
+			match event {
+				ButtonEvent::Press => {
+					spinner.spin();
+					setting.set(spinner.value).await;
+				}
+				ButtonEvent::HoldHalfSecond => spinner.reverse(),
+				// The value has already been applied, so it only needs saving
+				ButtonEvent::HoldFullSecond => {
+					let (setting, value) = (*setting, spinner.value);
+					self.editing = None;
+					CONFIG_UPDATED.signal(());
+					// Restarting the countdown from the new value loses the count, so it's asked first
+					if setting == NumberSetting::StartFrom
+						&& value != self.editing_from
+						&& read_counter_config().direction == Direction::Down
+					{
+						self.confirming = Some(Confirmation {
+							result: MenuResult::Direction(Direction::Down),
+							deadline: Instant::now() + CONFIRMATION_TIMEOUT,
+						});
+					}
+				}
+			}
+			return None;
+		}
+		match event {
+			ButtonEvent::Press => {
+				self.next();
+				None
+			}
+			ButtonEvent::HoldHalfSecond => self.select().await,
+			ButtonEvent::HoldFullSecond => Some(State::DeathToll),
+		}
+	}
+	/// The menu currently being shown
+	pub fn menu(&self) -> &'static Menu<'static> {
+		self.stack[self.depth].0
+	}
+	/// Index of the selected item in the current menu
+	pub fn index(&self) -> usize {
+		self.stack[self.depth].1
+	}
+	/// Whether the item at `index` in the current menu is active
+	pub fn is_active(&self, index: usize) -> bool {
+		index < u32::BITS as usize && self.active & (1 << index) != 0
+	}
+	/// Current value of the item at `index` in the current menu, if it's a number
+	pub fn value(&self, index: usize) -> u32 {
+		self.values.get(index).copied().unwrap_or(0)
+	}
+	/// Works out which items in the current menu are active, and the values of its numbers
+	async fn refresh_active(&mut self) {
+		let mut active = 0;
+		for (i, item) in self
+			.menu()
+			.items
+			.iter()
+			.enumerate()
+			.take(u32::BITS as usize)
+		{
+			if item.is_active().await {
+				active |= 1 << i;
+			}
+			if let MenuItem::Number(setting) = item {
+				self.values[i] = setting.get().await;
+			}
+		}
+		self.active = active;
+	}
+	/// Moves the selection to the next item, wrapping around at the end
+	pub fn next(&mut self) {
+		let (menu, index) = &mut self.stack[self.depth];
+		*index = (*index + 1) % menu.items.len();
+	}
+	/// Goes back to the parent menu. Returns false if already at the top level
+	pub fn back(&mut self) -> bool {
+		if self.depth == 0 {
+			return false;
+		}
+		self.depth -= 1;
+		true
+	}
+	/// Applies a result and saves the change
+	async fn apply(result: MenuResult) -> Option<State> {
+		let state = result.apply().await;
+		CONFIG_UPDATED.signal(());
+		state
+	}
+	/// Activates the selected item. Returns the state to switch to, if the menu should be left
+	pub async fn select(&mut self) -> Option<State> {
+		let menu = self.menu();
+		match &menu.items[self.index()] {
+			MenuItem::Submenu(submenu) => {
+				// Nesting is fixed at compile time, so this only fails if MAX_DEPTH is too small
+				if self.depth + 1 < MAX_DEPTH {
+					self.depth += 1;
+					self.stack[self.depth] = (submenu, 0);
+					// Start on the option that's currently applied
+					self.refresh_active().await;
+					if self.active != 0 {
+						self.stack[self.depth].1 = self.active.trailing_zeros() as usize;
+					}
+				}
+				None
+			}
+			MenuItem::Action(result) if result.needs_confirmation() => {
+				self.confirming = Some(Confirmation {
+					result: result.clone(),
+					deadline: Instant::now() + CONFIRMATION_TIMEOUT,
+				});
+				None
+			}
+			MenuItem::Action(result) => Self::apply(result.clone()).await,
+			MenuItem::Toggle(toggle) => {
+				toggle.set(!toggle.get());
+				CONFIG_UPDATED.signal(());
+				None
+			}
+			MenuItem::Number(setting) => {
+				self.editing_from = setting.get().await;
+				self.editing = Some((*setting, setting.spinner().await));
+				None
+			}
+			MenuItem::Text(setting) => {
+				self.typing = Some((*setting, TextEntry::new(setting.get().as_str())));
+				None
+			}
+			MenuItem::Back => {
+				if self.back() {
+					None
+				} else {
+					Some(State::DeathToll)
+				}
+			}
+		}
+	}
+}
+
 pub static MAIN_MENU: Menu<'static> = Menu::new(
-	"main",
-	Either::First(&[
-		Menu::new(
-			"RGB Mode",
-			Either::Second(&[
-				MenuResult::RgbMode(RgbMode::SineCycle(0.01)),
-				MenuResult::RgbMode(RgbMode::Continuous(1)),
-				MenuResult::RgbMode(RgbMode::Random(1)),
-				MenuResult::RgbMode(RgbMode::Fibonacci(1)),
-			]),
-		),
-		Menu::new(
-			"Brightness",
-			Either::Second(&RgbBrightness::map_to_menu_result()),
-		),
-		Menu::new("RGB Rate", Either::Second(&RgbRate::map_to_menu_result())),
-		Menu::new("Reset", Either::Second(&ResetOptions::map_to_menu_result())),
-	]),
+	"Menu",
+	&[
+		MenuItem::Submenu(Menu::new(
+			"Counter",
+			&[
+				MenuItem::Text(TextSetting::Title),
+				MenuItem::Number(NumberSetting::Target),
+				MenuItem::Number(NumberSetting::Milestones),
+				MenuItem::Submenu(Menu::new("Direction", &Direction::map_to_menu_items())),
+				MenuItem::Number(NumberSetting::StartFrom),
+				MenuItem::Number(NumberSetting::Step),
+				MenuItem::Submenu(Menu::new("Range", &Signedness::map_to_menu_items())),
+				MenuItem::Submenu(Menu::new("At Limit", &OverflowPolicy::map_to_menu_items())),
+				MenuItem::Toggle(Toggle::Buzzer),
+				MenuItem::Submenu(Menu::new("Saving", &SavePolicy::map_to_menu_items())),
+				MenuItem::Back,
+			],
+		)),
+		MenuItem::Submenu(Menu::new(
+			"LED",
+			&[
+				MenuItem::Submenu(Menu::new(
+					"RGB Mode",
+					&[
+						MenuItem::Action(MenuResult::RgbMode(RgbMode::SineCycle(0.01))),
+						MenuItem::Action(MenuResult::RgbMode(RgbMode::Continuous(1))),
+						MenuItem::Action(MenuResult::RgbMode(RgbMode::Random(1))),
+						MenuItem::Action(MenuResult::RgbMode(RgbMode::Fibonacci(1))),
+						MenuItem::Back,
+					],
+				)),
+				MenuItem::Number(NumberSetting::Brightness),
+				MenuItem::Number(NumberSetting::RgbRate),
+				MenuItem::Back,
+			],
+		)),
+		MenuItem::Submenu(Menu::new(
+			"Stats",
+			&[
+				MenuItem::Submenu(Menu::new("Tallies", &StatsPeriod::map_to_menu_items())),
+				MenuItem::Submenu(Menu::new("Graph", &GraphRange::map_to_menu_items())),
+				MenuItem::Submenu(Menu::new("Day Start", &DayStart::map_to_menu_items())),
+				MenuItem::Submenu(Menu::new("Week Start", &WeekStart::map_to_menu_items())),
+				MenuItem::Back,
+			],
+		)),
+		MenuItem::Submenu(Menu::new(
+			"Display",
+			&[
+				MenuItem::Number(NumberSetting::Contrast),
+				MenuItem::Toggle(Toggle::UpsideDown),
+				MenuItem::Toggle(Toggle::Invert),
+				MenuItem::Submenu(Menu::new("Menu Text", &MenuFont::map_to_menu_items())),
+				MenuItem::Number(NumberSetting::MenuTimeout),
+				MenuItem::Toggle(Toggle::Title),
+				MenuItem::Toggle(Toggle::Animate),
+				MenuItem::Number(NumberSetting::SleepAfter),
+				MenuItem::Back,
+			],
+		)),
+		MenuItem::Action(MenuResult::Diagnostics),
+		MenuItem::Submenu(Menu::new("Reset", &ResetOptions::map_to_menu_items())),
+		MenuItem::Back,
+	],
 );
-#[derive(Debug, Clone, EnumDiscriminants, PartialEq)]
-#[strum_discriminants(name(MenuType))]
+#[derive(Debug, Clone, PartialEq)]
 pub enum MenuResult {
 	RgbMode(RgbMode),
-	RgbBrightness(RgbBrightness),
-	RgbRate(RgbRate),
 	ResetOptions(ResetOptions),
+	Direction(Direction),
+	Signedness(Signedness),
+	OverflowPolicy(OverflowPolicy),
+	StatsPeriod(StatsPeriod),
+	GraphRange(GraphRange),
+	DayStart(DayStart),
+	WeekStart(WeekStart),
+	MenuFont(MenuFont),
+	SavePolicy(SavePolicy),
+	Diagnostics,
 }
 #[derive(Debug, Clone, Copy, IntoStaticStr, VariantArray, PartialEq)]
 pub enum ResetOptions {
 	All,
 	Rgb,
 	Count,
+	Stats,
 }
-#[derive(Debug, Clone, Copy, IntoStaticStr, VariantArray, PartialEq)]
-pub enum RgbBrightness {
-	Low = 10,
-	Medium = 100,
-	High = 200,
-	Max = 255,
-}
-impl ConstDefault for RgbBrightness {
-	const DEFAULT: Self = Self::Low;
-}
-/// Values roughly model an exponential curve (rounded to the nearest integer)
-#[derive(Debug, Clone, Copy, IntoStaticStr, VariantArray, PartialEq)]
-pub enum RgbRate {
-	VerySlow = 1,
-	Slow = 3,
-	Moderate = 7,
-	Fast = 20,
-	VeryFast = 55,
-}
-impl ConstDefault for RgbRate {
-	const DEFAULT: Self = Self::Moderate;
-}
-/// Nasty macro that allows for a constant mapping of `T` to `MenuResult<T>`
-macro_rules! implement_map_to_menu_result {
+/// Nasty macro that allows for a constant mapping of `T` to a menu of `MenuResult<T>` actions, followed by a back entry
+macro_rules! implement_map_to_menu_items {
 	($x:ident) => {
 		impl $x {
-			pub const fn map_to_menu_result() -> [MenuResult; $x::VARIANTS.len()] {
-				let mut s = [const { MaybeUninit::<MenuResult>::uninit() }; $x::VARIANTS.len()];
+			pub const fn map_to_menu_items() -> [MenuItem<'static>; $x::VARIANTS.len() + 1] {
+				let mut s =
+					[const { MaybeUninit::<MenuItem<'static>>::uninit() }; $x::VARIANTS.len() + 1];
 				let mut i = 0;
 				while i < $x::VARIANTS.len() {
-					s[i].write(MenuResult::$x($x::VARIANTS[i]));
+					s[i].write(MenuItem::Action(MenuResult::$x($x::VARIANTS[i])));
 					i += 1;
 				}
-				// Safe as MaybeUnit<MenuResult> is guaranteed to have the same size and alignment as MenuResult
-				unsafe { s.as_ptr().cast::<[MenuResult; $x::VARIANTS.len()]>().read() }
+				s[i].write(MenuItem::Back);
+				// Safe as MaybeUnit<MenuItem> is guaranteed to have the same size and alignment as MenuItem
+				unsafe {
+					s.as_ptr()
+						.cast::<[MenuItem<'static>; $x::VARIANTS.len() + 1]>()
+						.read()
+				}
 			}
 		}
 	};
 }
-implement_map_to_menu_result!(RgbBrightness);
-implement_map_to_menu_result!(RgbRate);
-implement_map_to_menu_result!(ResetOptions);
+implement_map_to_menu_items!(ResetOptions);
+implement_map_to_menu_items!(Direction);
+implement_map_to_menu_items!(Signedness);
+implement_map_to_menu_items!(OverflowPolicy);
+implement_map_to_menu_items!(StatsPeriod);
+implement_map_to_menu_items!(GraphRange);
+implement_map_to_menu_items!(DayStart);
+implement_map_to_menu_items!(WeekStart);
+implement_map_to_menu_items!(MenuFont);
+implement_map_to_menu_items!(SavePolicy);
 impl From<MenuResult> for &'static str {
 	fn from(value: MenuResult) -> Self {
 		match value {
 			MenuResult::RgbMode(x) => x.into(),
-			MenuResult::RgbBrightness(x) => x.into(),
-			MenuResult::RgbRate(x) => x.into(),
 			MenuResult::ResetOptions(x) => x.into(),
+			MenuResult::Direction(x) => x.into(),
+			MenuResult::Signedness(x) => x.into(),
+			MenuResult::OverflowPolicy(x) => x.into(),
+			MenuResult::StatsPeriod(x) => x.into(),
+			MenuResult::GraphRange(x) => x.into(),
+			MenuResult::DayStart(x) => x.into(),
+			MenuResult::WeekStart(x) => x.into(),
+			MenuResult::MenuFont(x) => x.into(),
+			MenuResult::SavePolicy(x) => x.into(),
+			MenuResult::Diagnostics => "Diagnostics",
+		}
+	}
+}
+
+impl MenuResult {
+	/// Applies the result. Returns the state to switch to, if the menu should be left
+	pub async fn apply(self) -> Option<State> {
+		match self {
+			MenuResult::RgbMode(mode) => RGB_CONFIG.lock().await.set_mode(mode),
+			MenuResult::ResetOptions(o) => reset(o).await,
+			MenuResult::Direction(direction) => {
+				let config = CounterConfig {
+					direction,
+					..read_counter_config()
+				};
+				write_counter_config(config);
+				// A countdown needs to start from the configured value
+				if direction == Direction::Down {
+					write_count(config.initial_count());
+				}
+			}
+			MenuResult::Signedness(signedness) => {
+				write_counter_config(CounterConfig {
+					signedness,
+					..read_counter_config()
+				});
+				// A negative count can't be kept when switching to positive only
+				fit_count_to_range();
+			}
+			MenuResult::OverflowPolicy(overflow) => write_counter_config(CounterConfig {
+				overflow,
+				..read_counter_config()
+			}),
+			MenuResult::StatsPeriod(period) => return Some(State::Stats(period)),
+			MenuResult::GraphRange(range) => return Some(State::Graph(range)),
+			MenuResult::DayStart(day_start) => write_stats_config(StatsConfig {
+				day_start,
+				..read_stats_config()
+			}),
+			MenuResult::WeekStart(week_start) => write_stats_config(StatsConfig {
+				week_start,
+				..read_stats_config()
+			}),
+			MenuResult::MenuFont(menu_font) => write_display_config(DisplayConfig {
+				menu_font,
+				..read_display_config()
+			}),
+			MenuResult::SavePolicy(policy) => write_save_config(SaveConfig { policy }),
+			MenuResult::Diagnostics => return Some(State::Diagnostics(0)),
 		}
+		None
 	}
 }
 
-pub async fn default_index<'a>(m: &Menu<'a>) -> usize {
-	if let Either::Second(x) = &m.items {
-		let tp = MenuType::from(&x[0]);
-		let rgb_config = RGB_CONFIG.lock().await.clone();
-		match tp {
-			MenuType::RgbMode => x
-				.iter()
-				.position(|y| *y == MenuResult::RgbMode(rgb_config.rgb_mode.clone()))
-				.unwrap_or(0),
-			MenuType::RgbBrightness => x
-				.iter()
-				.position(|y| *y == MenuResult::RgbBrightness(rgb_config.rgb_brightness))
-				.unwrap_or(0),
-			MenuType::RgbRate => x
-				.iter()
-				.position(|y| *y == MenuResult::RgbRate(rgb_config.rgb_rate_modifier))
-				.unwrap_or(0),
-			MenuType::ResetOptions => 0,
-		}
-	} else {
-		0
+impl MenuResult {
+	/// Whether the result can't be undone, so needs confirming first
+	pub fn needs_confirmation(&self) -> bool {
+		matches!(
+			self,
+			MenuResult::ResetOptions(_) | MenuResult::Direction(Direction::Down)
+		)
+	}
+	/// Question asked before applying a result that needs confirming
+	pub fn confirmation_prompt<'b>(&self, buf: &'b mut [u8]) -> &'b str {
+		match self {
+			MenuResult::ResetOptions(ResetOptions::All) => "Reset\neverything?",
+			MenuResult::ResetOptions(ResetOptions::Count) => {
+				format_no_std::show(buf, format_args!("Reset count\n{}?", read_count()))
+					.unwrap_or("Reset count?")
+			}
+			MenuResult::ResetOptions(ResetOptions::Rgb) => "Reset RGB\nsettings?",
+			MenuResult::ResetOptions(ResetOptions::Stats) => "Reset stats?",
+			// Starting a countdown replaces the count
+			MenuResult::Direction(Direction::Down) => format_no_std::show(
+				buf,
+				format_args!("Count down\nfrom {}?", read_counter_config().start),
+			)
+			.unwrap_or("Count down?"),
+			_ => "Are you sure?",
+		}
+	}
+	/// Whether this is the option that's currently applied
+	pub async fn is_active(&self) -> bool {
+		let counter_config = read_counter_config();
+		let stats_config = read_stats_config();
+		match self {
+			MenuResult::RgbMode(mode) => RGB_CONFIG.lock().await.rgb_mode == *mode,
+			MenuResult::Direction(direction) => counter_config.direction == *direction,
+			MenuResult::Signedness(signedness) => counter_config.signedness == *signedness,
+			MenuResult::OverflowPolicy(overflow) => counter_config.overflow == *overflow,
+			MenuResult::DayStart(day_start) => stats_config.day_start == *day_start,
+			MenuResult::WeekStart(week_start) => stats_config.week_start == *week_start,
+			MenuResult::MenuFont(menu_font) => read_display_config().menu_font == *menu_font,
+			MenuResult::SavePolicy(policy) => read_save_config().policy == *policy,
+			// These do something rather than choosing a setting
+			MenuResult::ResetOptions(_)
+			| MenuResult::StatsPeriod(_)
+			| MenuResult::GraphRange(_)
+			| MenuResult::Diagnostics => false,
+		}
 	}
 }
@@ -0,0 +1,160 @@
+//! Flash kept in RAM, so the storage tasks can be run off the device. Faults can be injected
+//! to see how they cope with losing power part way through a write, or with a bit going bad
+
+use embedded_storage_async::nor_flash::{
+	ErrorType, MultiwriteNorFlash, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+};
+
+use crate::storage::PAGE_SIZE;
+
+/// Why an operation on the mock flash failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MockFlashError {
+	/// The range isn't inside the flash
+	OutOfBounds,
+	/// The offset or length isn't a multiple of the read, write or erase size
+	NotAligned,
+	/// Power was cut, so nothing works until it's restored
+	PowerLost,
+}
+impl NorFlashError for MockFlashError {
+	fn kind(&self) -> NorFlashErrorKind {
+		match self {
+			Self::OutOfBounds => NorFlashErrorKind::OutOfBounds,
+			Self::NotAligned => NorFlashErrorKind::NotAligned,
+			Self::PowerLost => NorFlashErrorKind::Other,
+		}
+	}
+}
+
+/// `SIZE` bytes of NOR flash with the same page size as the ESP32-C3's.
+/// Like real NOR flash, writing can only clear bits, and erasing sets a whole page back to 0xFF
+#[derive(Clone)]
+pub struct MockFlash<const SIZE: usize> {
+	data: [u8; SIZE],
+	/// Writes and erases left before the power is cut, if it's going to be
+	power_cut_in: Option<u32>,
+	powered: bool,
+	/// Writes and erases done since it was created
+	operations: u32,
+}
+impl<const SIZE: usize> MockFlash<SIZE> {
+	/// Flash that has just been erased
+	pub const fn new() -> Self {
+		Self {
+			data: [0xFF; SIZE],
+			power_cut_in: None,
+			powered: true,
+			operations: 0,
+		}
+	}
+	/// Cuts the power during the write or erase after `operations` more have finished.
+	/// Only part of that one is done, and everything fails until `restore_power` is called
+	pub fn cut_power_after(&mut self, operations: u32) {
+		self.power_cut_in = Some(operations);
+	}
+	/// Powers the flash back up, keeping whatever was written before the power was cut
+	pub fn restore_power(&mut self) {
+		self.power_cut_in = None;
+		self.powered = true;
+	}
+	/// Flips a single bit, as if the flash had worn out or been disturbed
+	pub fn flip_bit(&mut self, offset: u32, bit: u8) {
+		self.data[offset as usize] ^= 1 << (bit % 8);
+	}
+	/// Writes and erases done so far, to pick when to cut the power
+	pub fn operations(&self) -> u32 {
+		self.operations
+	}
+	/// Everything stored in the flash
+	pub fn as_bytes(&self) -> &[u8] {
+		&self.data
+	}
+	/// Checks a range is inside the flash and aligned to `align` bytes
+	fn check(&self, offset: u32, length: usize, align: usize) -> Result<(), MockFlashError> {
+		let offset = offset as usize;
+		if offset.checked_add(length).is_none_or(|end| end > SIZE) {
+			Err(MockFlashError::OutOfBounds)
+		} else if !offset.is_multiple_of(align) || !length.is_multiple_of(align) {
+			Err(MockFlashError::NotAligned)
+		} else if !self.powered {
+			Err(MockFlashError::PowerLost)
+		} else {
+			Ok(())
+		}
+	}
+	/// Counts a write or erase, returning whether the power is cut during it
+	fn power_cut(&mut self) -> bool {
+		self.operations += 1;
+		match self.power_cut_in {
+			Some(0) => {
+				self.powered = false;
+				true
+			}
+			Some(n) => {
+				self.power_cut_in = Some(n - 1);
+				false
+			}
+			None => false,
+		}
+	}
+}
+impl<const SIZE: usize> Default for MockFlash<SIZE> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+impl<const SIZE: usize> ErrorType for MockFlash<SIZE> {
+	type Error = MockFlashError;
+}
+impl<const SIZE: usize> ReadNorFlash for MockFlash<SIZE> {
+	const READ_SIZE: usize = 1;
+	async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+		self.check(offset, bytes.len(), Self::READ_SIZE)?;
+		let offset = offset as usize;
+		bytes.copy_from_slice(&self.data[offset..offset + bytes.len()]);
+		Ok(())
+	}
+	fn capacity(&self) -> usize {
+		SIZE
+	}
+}
+impl<const SIZE: usize> NorFlash for MockFlash<SIZE> {
+	const WRITE_SIZE: usize = 4;
+	const ERASE_SIZE: usize = PAGE_SIZE as usize;
+	async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+		if to < from {
+			return Err(MockFlashError::OutOfBounds);
+		}
+		self.check(from, (to - from) as usize, Self::ERASE_SIZE)?;
+		let cut = self.power_cut();
+		let range = &mut self.data[from as usize..to as usize];
+		if cut {
+			// Only the first half of the range is erased before the power goes
+			let half = range.len() / 2;
+			range[..half].fill(0xFF);
+			return Err(MockFlashError::PowerLost);
+		}
+		range.fill(0xFF);
+		Ok(())
+	}
+	async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+		self.check(offset, bytes.len(), Self::WRITE_SIZE)?;
+		// Only the first half of the words are written before the power goes
+		let (written, cut) = if self.power_cut() {
+			(bytes.len() / Self::WRITE_SIZE / 2 * Self::WRITE_SIZE, true)
+		} else {
+			(bytes.len(), false)
+		};
+		let offset = offset as usize;
+		for (stored, byte) in self.data[offset..offset + written].iter_mut().zip(bytes) {
+			*stored &= byte;
+		}
+		if cut {
+			Err(MockFlashError::PowerLost)
+		} else {
+			Ok(())
+		}
+	}
+}
+impl<const SIZE: usize> MultiwriteNorFlash for MockFlash<SIZE> {}
@@ -0,0 +1,64 @@
+//! Rolls the digits of the count from one value to the next, like an odometer
+
+use embassy_time::{Duration, Instant};
+
+use crate::count::Count;
+
+/// How long a change takes to roll into place
+const ROLL_TIME: Duration = Duration::from_millis(240);
+/// Time between frames while rolling
+const FRAME_TIME: Duration = Duration::from_millis(30);
+
+/// A point part way through rolling from one count to another
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frame {
+	pub from: Count,
+	/// How far the digits have moved, out of 1000
+	pub progress: u32,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Odometer {
+	/// The count that was last drawn, or rolled to
+	shown: Option<Count>,
+	/// The count being rolled from, and when it started
+	roll: Option<(Count, Instant)>,
+}
+impl Odometer {
+	pub const fn new() -> Self {
+		Self {
+			shown: None,
+			roll: None,
+		}
+	}
+	/// Notes the count about to be drawn, starting a roll if it's changed. A change part way through
+	/// a roll starts again from where that was heading
+	pub fn update(&mut self, count: Count, now: Instant, animate: bool) {
+		if let Some(shown) = self.shown
+			&& shown != count
+		{
+			self.roll = animate.then_some((shown, now));
+		}
+		self.shown = Some(count);
+		if self.roll.is_some_and(|(_, start)| now >= start + ROLL_TIME) {
+			self.roll = None;
+		}
+	}
+	/// Where the roll is up to, if there is one
+	pub fn frame(&self, now: Instant) -> Option<Frame> {
+		let (from, start) = self.roll?;
+		let elapsed = (now - start).as_millis().min(ROLL_TIME.as_millis());
+		let t = (elapsed * 1000 / ROLL_TIME.as_millis()) as u32;
+		// Eases out, so the digits settle into place
+		let remaining = 1000 - t;
+		Some(Frame {
+			from,
+			progress: 1000 - remaining * remaining / 1000,
+		})
+	}
+	/// When the next frame should be drawn, while rolling
+	pub fn next_frame(&self, now: Instant) -> Option<Instant> {
+		let (_, start) = self.roll?;
+		Some((now + FRAME_TIME).min(start + ROLL_TIME))
+	}
+}
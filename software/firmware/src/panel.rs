@@ -0,0 +1,85 @@
+//! What the main loop needs from a display, so it works with whichever panel the board has
+
+use crate::sh1106::Sh1106;
+use display_interface::{AsyncWriteOnlyDataCommand, DisplayError};
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::DrawTarget};
+use ssd1306::{
+	Ssd1306Async,
+	mode::{BufferedGraphicsModeAsync, DisplayConfigAsync},
+	prelude::{Brightness, DisplayRotation},
+	size::DisplaySizeAsync,
+};
+
+/// A buffered display that's drawn to and then flushed to the panel
+// Only ever awaited from the main task, so the futures don't need to be Send
+#[allow(async_fn_in_trait)]
+pub trait Panel: DrawTarget<Color = BinaryColor, Error = DisplayError> {
+	/// Sets the panel up and clears it. Also used to recover it after it stops responding
+	async fn init(&mut self) -> Result<(), DisplayError>;
+	/// Sends everything drawn since the last flush to the panel
+	async fn flush(&mut self) -> Result<(), DisplayError>;
+	fn clear_buffer(&mut self);
+	/// Contrast out of 255, where 0 is as dim as the panel goes
+	async fn set_contrast(&mut self, contrast: u8) -> Result<(), DisplayError>;
+	async fn set_display_on(&mut self, on: bool) -> Result<(), DisplayError>;
+	async fn set_invert(&mut self, invert: bool) -> Result<(), DisplayError>;
+	async fn set_rotation(&mut self, rotation: DisplayRotation) -> Result<(), DisplayError>;
+}
+
+impl<DI, SIZE> Panel for Ssd1306Async<DI, SIZE, BufferedGraphicsModeAsync<SIZE>>
+where
+	DI: AsyncWriteOnlyDataCommand,
+	SIZE: DisplaySizeAsync,
+{
+	async fn init(&mut self) -> Result<(), DisplayError> {
+		DisplayConfigAsync::init(self).await
+	}
+	async fn flush(&mut self) -> Result<(), DisplayError> {
+		Ssd1306Async::flush(self).await
+	}
+	fn clear_buffer(&mut self) {
+		Ssd1306Async::clear_buffer(self)
+	}
+	async fn set_contrast(&mut self, contrast: u8) -> Result<(), DisplayError> {
+		let brightness = if contrast == 0 {
+			// Also shortens the precharge period, which dims it further
+			Brightness::DIMMEST
+		} else {
+			Brightness::custom(0x2, contrast)
+		};
+		self.set_brightness(brightness).await
+	}
+	async fn set_display_on(&mut self, on: bool) -> Result<(), DisplayError> {
+		Ssd1306Async::set_display_on(self, on).await
+	}
+	async fn set_invert(&mut self, invert: bool) -> Result<(), DisplayError> {
+		Ssd1306Async::set_invert(self, invert).await
+	}
+	async fn set_rotation(&mut self, rotation: DisplayRotation) -> Result<(), DisplayError> {
+		DisplayConfigAsync::set_rotation(self, rotation).await
+	}
+}
+
+impl<DI: AsyncWriteOnlyDataCommand> Panel for Sh1106<DI> {
+	async fn init(&mut self) -> Result<(), DisplayError> {
+		Sh1106::init(self).await
+	}
+	async fn flush(&mut self) -> Result<(), DisplayError> {
+		Sh1106::flush(self).await
+	}
+	fn clear_buffer(&mut self) {
+		Sh1106::clear_buffer(self)
+	}
+	async fn set_contrast(&mut self, contrast: u8) -> Result<(), DisplayError> {
+		Sh1106::set_contrast(self, contrast).await
+	}
+	async fn set_display_on(&mut self, on: bool) -> Result<(), DisplayError> {
+		Sh1106::set_display_on(self, on).await
+	}
+	async fn set_invert(&mut self, invert: bool) -> Result<(), DisplayError> {
+		Sh1106::set_invert(self, invert).await
+	}
+	async fn set_rotation(&mut self, rotation: DisplayRotation) -> Result<(), DisplayError> {
+		Sh1106::set_rotation(self, rotation).await
+	}
+}
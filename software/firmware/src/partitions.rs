@@ -0,0 +1,214 @@
+//! Reads the partition table flashed alongside the firmware, to find where data can be stored
+
+use core::ops::Range;
+
+use embedded_storage_async::nor_flash::ReadNorFlash;
+
+use crate::md5::Md5;
+
+/// Where the partition table is written, just after the bootloader
+const TABLE_OFFSET: u32 = 0x8000;
+/// Most entries the table has room for, leaving room for its checksum after them
+const MAX_ENTRIES: u32 = 95;
+const ENTRY_SIZE: usize = 32;
+/// Start of every entry. The table ends at the first entry without it (its checksum, or erased flash)
+const ENTRY_MAGIC: [u8; 2] = [0xAA, 0x50];
+/// Start of the entry after the last, which holds the MD5 digest of the entries before it in its last
+/// 16 bytes. Tables made without one aren't checked, like by the bootloader
+const MD5_MAGIC: [u8; 2] = [0xEB, 0xEB];
+
+/// Partition type for apps, such as this firmware
+pub const APP: u8 = 0x00;
+/// Partition type for data, rather than apps
+pub const DATA: u8 = 0x01;
+/// Data partition subtype for NVS, which is free as WiFi isn't used
+pub const NVS: u8 = 0x02;
+
+/// An entry in the partition table
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Partition {
+	pub kind: u8,
+	pub subtype: u8,
+	/// Where the partition starts in flash
+	pub offset: u32,
+	/// Size of the partition in bytes
+	pub size: u32,
+}
+impl Partition {
+	fn from_entry(entry: &[u8; ENTRY_SIZE]) -> Self {
+		Self {
+			kind: entry[2],
+			subtype: entry[3],
+			offset: u32::from_le_bytes([entry[4], entry[5], entry[6], entry[7]]),
+			size: u32::from_le_bytes([entry[8], entry[9], entry[10], entry[11]]),
+		}
+	}
+	/// The flash the partition covers
+	pub fn range(&self) -> Range<u32> {
+		self.offset..self.offset.saturating_add(self.size)
+	}
+	/// Whether any of the flash is in both partitions
+	pub fn overlaps(&self, other: &Partition) -> bool {
+		self.offset < other.range().end && other.offset < self.range().end
+	}
+}
+
+/// Why the partition found can't be used
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TableError<E> {
+	/// The table couldn't be read
+	Flash(E),
+	/// The table doesn't match its MD5 digest, so it's been damaged
+	Checksum,
+	/// The data partition overlaps an app, so writing to it would damage the firmware
+	OverlapsApp,
+}
+
+async fn read_entry<F: ReadNorFlash>(
+	flash: &mut F,
+	i: u32,
+) -> Result<[u8; ENTRY_SIZE], TableError<F::Error>> {
+	let mut entry = [0u8; ENTRY_SIZE];
+	flash
+		.read(TABLE_OFFSET + i * ENTRY_SIZE as u32, &mut entry)
+		.await
+		.map_err(TableError::Flash)?;
+	Ok(entry)
+}
+
+/// Finds the first partition with the given type and subtype, if the table has one.
+/// The whole table is checked against its digest first, and a data partition is only returned
+/// if it doesn't overlap an app
+pub async fn find<F: ReadNorFlash>(
+	flash: &mut F,
+	kind: u8,
+	subtype: u8,
+) -> Result<Option<Partition>, TableError<F::Error>> {
+	let mut md5 = Md5::new();
+	let mut entries = 0;
+	let mut found = None;
+	// One more than the most entries, for the digest after them
+	for i in 0..=MAX_ENTRIES {
+		let entry = read_entry(flash, i).await?;
+		match [entry[0], entry[1]] {
+			ENTRY_MAGIC if i < MAX_ENTRIES => {
+				md5.update(&entry);
+				entries += 1;
+				let partition = Partition::from_entry(&entry);
+				if found.is_none() && partition.kind == kind && partition.subtype == subtype {
+					found = Some(partition);
+				}
+			}
+			MD5_MAGIC => {
+				if entry[16..] != md5.finish() {
+					return Err(TableError::Checksum);
+				}
+				break;
+			}
+			_ => break,
+		}
+	}
+	if let Some(found) = found
+		&& found.kind != APP
+	{
+		for i in 0..entries {
+			let partition = Partition::from_entry(&read_entry(flash, i).await?);
+			if partition.kind == APP && partition.overlaps(&found) {
+				return Err(TableError::OverlapsApp);
+			}
+		}
+	}
+	Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+	use embassy_futures::block_on;
+	use embedded_storage_async::nor_flash::NorFlash;
+
+	use super::*;
+	use crate::mock_flash::MockFlash;
+
+	/// Flash up to the end of the partition table
+	type Mock = MockFlash<0x9000>;
+
+	fn entry(kind: u8, subtype: u8, offset: u32, size: u32) -> [u8; ENTRY_SIZE] {
+		let mut entry = [0; ENTRY_SIZE];
+		entry[..2].copy_from_slice(&ENTRY_MAGIC);
+		entry[2] = kind;
+		entry[3] = subtype;
+		entry[4..8].copy_from_slice(&offset.to_le_bytes());
+		entry[8..12].copy_from_slice(&size.to_le_bytes());
+		entry
+	}
+
+	/// Flash holding a table of `entries`, followed by their digest if `digest` is set
+	fn table(entries: &[[u8; ENTRY_SIZE]], digest: bool) -> Mock {
+		let mut flash = Mock::new();
+		let mut md5 = Md5::new();
+		for (i, entry) in entries.iter().enumerate() {
+			md5.update(entry);
+			block_on(flash.write(TABLE_OFFSET + (i * ENTRY_SIZE) as u32, entry)).unwrap();
+		}
+		if digest {
+			let mut entry = [0xFF; ENTRY_SIZE];
+			entry[..2].copy_from_slice(&MD5_MAGIC);
+			entry[16..].copy_from_slice(&md5.finish());
+			let offset = TABLE_OFFSET + (entries.len() * ENTRY_SIZE) as u32;
+			block_on(flash.write(offset, &entry)).unwrap();
+		}
+		flash
+	}
+
+	/// The default table for a single app
+	fn default_entries() -> [[u8; ENTRY_SIZE]; 3] {
+		[
+			entry(DATA, NVS, 0x9000, 0x6000),
+			entry(DATA, 0x01, 0xF000, 0x1000),
+			entry(APP, 0x00, 0x10000, 0x100000),
+		]
+	}
+
+	#[test]
+	fn finds_nvs_in_checked_table() {
+		let mut flash = table(&default_entries(), true);
+		assert_eq!(
+			block_on(find(&mut flash, DATA, NVS)),
+			Ok(Some(Partition {
+				kind: DATA,
+				subtype: NVS,
+				offset: 0x9000,
+				size: 0x6000,
+			}))
+		);
+		// Tables made without a digest are still read
+		let mut flash = table(&default_entries(), false);
+		assert!(matches!(block_on(find(&mut flash, DATA, NVS)), Ok(Some(_))));
+	}
+
+	#[test]
+	fn damaged_table_is_refused() {
+		let mut flash = table(&default_entries(), true);
+		// Moves the NVS partition's offset
+		flash.flip_bit(TABLE_OFFSET + 5, 4);
+		assert_eq!(
+			block_on(find(&mut flash, DATA, NVS)),
+			Err(TableError::Checksum)
+		);
+	}
+
+	#[test]
+	fn nvs_over_app_is_refused() {
+		let mut entries = default_entries();
+		entries[0] = entry(DATA, NVS, 0x20000, 0x6000);
+		let mut flash = table(&entries, true);
+		assert_eq!(
+			block_on(find(&mut flash, DATA, NVS)),
+			Err(TableError::OverlapsApp)
+		);
+		// Right up against the app is fine
+		entries[0] = entry(DATA, NVS, 0xA000, 0x6000);
+		let mut flash = table(&entries, true);
+		assert!(matches!(block_on(find(&mut flash, DATA, NVS)), Ok(Some(_))));
+	}
+}
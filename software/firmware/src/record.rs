@@ -0,0 +1,222 @@
+//! Encodes the records saved to flash one field at a time, after a header giving the record's version.
+//!
+//! Every field checks its bytes as it's read back, so a record written by other firmware can't
+//! produce an invalid enum or bool. A field that's invalid, or missing because the record was saved
+//! before it was added, is left at its default rather than losing the whole record.
+//! Fields can be added to the end of a record freely, but removing one or changing what it means
+//! needs a new version, with `Record::read_fields` migrating records saved as the old one
+
+use sequential_storage::map::SerializationError;
+
+use crate::const_default::ConstDefault;
+
+/// Start of every record, so records copied straight from memory by older firmware aren't misread
+const MARKER: u8 = 0xA5;
+
+/// Writes fields to the buffer an item is saved from
+pub struct Writer<'a> {
+	buffer: &'a mut [u8],
+	len: usize,
+	/// Set when a field didn't fit in the buffer
+	overflowed: bool,
+}
+impl Writer<'_> {
+	pub fn bytes(&mut self, bytes: &[u8]) {
+		match self.buffer.get_mut(self.len..self.len + bytes.len()) {
+			Some(dest) => {
+				dest.copy_from_slice(bytes);
+				self.len += bytes.len();
+			}
+			None => self.overflowed = true,
+		}
+	}
+}
+
+/// Reads fields from the bytes of a stored item
+pub struct Reader<'a> {
+	buffer: &'a [u8],
+}
+impl Reader<'_> {
+	/// The next `N` bytes, or None if the record ends before them
+	pub fn bytes<const N: usize>(&mut self) -> Option<[u8; N]> {
+		match self.buffer.split_first_chunk::<N>() {
+			Some((bytes, rest)) => {
+				self.buffer = rest;
+				Some(*bytes)
+			}
+			None => {
+				self.buffer = &[];
+				None
+			}
+		}
+	}
+}
+
+/// A value that can be written to a record and read back
+pub trait Field: Sized {
+	fn write(&self, w: &mut Writer);
+	/// Reads the value back, or returns None if the bytes aren't a valid value.
+	/// All of the value's bytes are used up either way, so the fields after it can still be read
+	fn read(r: &mut Reader) -> Option<Self>;
+}
+
+/// Reads a field over its current (default) value, leaving it as it is if it's missing or invalid
+pub fn read_into<T: Field>(field: &mut T, r: &mut Reader) {
+	if let Some(value) = T::read(r) {
+		*field = value;
+	}
+}
+
+macro_rules! impl_field_for_int {
+	($($x:ty),*) => {
+		$(impl Field for $x {
+			fn write(&self, w: &mut Writer) {
+				w.bytes(&self.to_le_bytes());
+			}
+			fn read(r: &mut Reader) -> Option<Self> {
+				r.bytes().map(<$x>::from_le_bytes)
+			}
+		}
+		/// Written with its length first, so an array stored with a different length is ignored
+		impl<const N: usize> Field for [$x; N] {
+			fn write(&self, w: &mut Writer) {
+				(N as u8).write(w);
+				for x in self {
+					x.write(w);
+				}
+			}
+			fn read(r: &mut Reader) -> Option<Self> {
+				let len = u8::read(r)?;
+				let mut items = [0; N];
+				let mut complete = len as usize == N;
+				for i in 0..len as usize {
+					match (<$x>::read(r), items.get_mut(i)) {
+						(Some(x), Some(item)) => *item = x,
+						_ => complete = false,
+					}
+				}
+				complete.then_some(items)
+			}
+		})*
+	};
+}
+impl_field_for_int!(u8, u32, u64);
+
+impl Field for bool {
+	fn write(&self, w: &mut Writer) {
+		(*self as u8).write(w);
+	}
+	fn read(r: &mut Reader) -> Option<Self> {
+		match u8::read(r)? {
+			0 => Some(false),
+			1 => Some(true),
+			_ => None,
+		}
+	}
+}
+
+/// Implements `Field` for enums without data, stored as their discriminant. Only discriminants
+/// of existing variants are read back, so variants can be reordered but not renumbered
+macro_rules! impl_field_for_enum {
+	($($x:ty),* $(,)?) => {
+		$(impl $crate::record::Field for $x {
+			fn write(&self, w: &mut $crate::record::Writer) {
+				$crate::record::Field::write(&(*self as u8), w);
+			}
+			fn read(r: &mut $crate::record::Reader) -> Option<Self> {
+				let discriminant = <u8 as $crate::record::Field>::read(r)?;
+				<Self as strum::VariantArray>::VARIANTS
+					.iter()
+					.copied()
+					.find(|&x| x as u8 == discriminant)
+			}
+		})*
+	};
+}
+pub(crate) use impl_field_for_enum;
+
+/// A struct saved to flash as a versioned list of fields
+pub trait Record: ConstDefault {
+	/// Only needs bumping when a field is removed or changes meaning
+	const VERSION: u8;
+	fn write_fields(&self, w: &mut Writer);
+	/// Reads the fields of a record saved as `version` over the defaults in `self`
+	fn read_fields(&mut self, version: u8, r: &mut Reader) -> Result<(), SerializationError>;
+}
+
+/// Writes the header, then the record's fields
+pub fn serialize<T: Record>(record: &T, buffer: &mut [u8]) -> Result<usize, SerializationError> {
+	let mut w = Writer {
+		buffer,
+		len: 0,
+		overflowed: false,
+	};
+	w.bytes(&[MARKER, T::VERSION]);
+	record.write_fields(&mut w);
+	if w.overflowed {
+		Err(SerializationError::BufferTooSmall)
+	} else {
+		Ok(w.len)
+	}
+}
+
+/// Reads a record back. Fails if it isn't in this format, or was saved as a version this firmware
+/// doesn't know how to read, so the record is treated as missing
+pub fn deserialize<T: Record>(buffer: &[u8]) -> Result<T, SerializationError> {
+	let [MARKER, version, ref fields @ ..] = *buffer else {
+		return Err(SerializationError::InvalidFormat);
+	};
+	let mut record = T::DEFAULT;
+	record.read_fields(version, &mut Reader { buffer: fields })?;
+	Ok(record)
+}
+
+/// Implements `Record` and `Value` for a struct by saving the listed fields in order.
+/// Records saved as any other version are ignored, so a struct that needs migrating implements
+/// `Record` itself instead
+macro_rules! impl_record {
+	($x:ty, version $version:literal, [$($($field:ident).+),* $(,)?]) => {
+		impl $crate::record::Record for $x {
+			const VERSION: u8 = $version;
+			fn write_fields(&self, w: &mut $crate::record::Writer) {
+				$($crate::record::Field::write(&self.$($field).+, w);)*
+			}
+			fn read_fields(
+				&mut self,
+				version: u8,
+				r: &mut $crate::record::Reader,
+			) -> Result<(), sequential_storage::map::SerializationError> {
+				if version != $version {
+					return Err(sequential_storage::map::SerializationError::InvalidFormat);
+				}
+				$($crate::record::read_into(&mut self.$($field).+, r);)*
+				Ok(())
+			}
+		}
+		$crate::record::impl_value_as_record!($x);
+	};
+}
+pub(crate) use impl_record;
+
+/// Implements `Value` for a type that implements `Record`
+macro_rules! impl_value_as_record {
+	($x:ty) => {
+		impl<'a> sequential_storage::map::Value<'a> for $x {
+			fn serialize_into(
+				&self,
+				buffer: &mut [u8],
+			) -> Result<usize, sequential_storage::map::SerializationError> {
+				$crate::record::serialize(self, buffer)
+			}
+			fn deserialize_from(
+				buffer: &'a [u8],
+			) -> Result<Self, sequential_storage::map::SerializationError>
+			where
+				Self: Sized,
+			{
+				$crate::record::deserialize(buffer)
+			}
+		}
+	};
+}
+pub(crate) use impl_value_as_record;
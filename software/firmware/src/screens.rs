@@ -0,0 +1,856 @@
+//! Renders screens that show more than a line or two of text
+
+use embedded_graphics::{
+	Drawable,
+	mono_font::{
+		MonoFont, MonoTextStyle,
+		iso_8859_9::{FONT_6X10, FONT_9X15, FONT_10X20},
+	},
+	pixelcolor::BinaryColor,
+	prelude::{DrawTarget, DrawTargetExt, Point, Primitive, Size},
+	primitives::{Line, PrimitiveStyle, PrimitiveStyleBuilder, Rectangle},
+	text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+
+use embassy_time::Duration;
+
+use crate::{
+	clock,
+	count::{Count, CounterConfig, Direction},
+	diagnostics,
+	display::{MenuFont, read_display_config},
+	highlight::{Highlight, current_highlight},
+	menustate::{Confirmation, Navigator, NumberSetting, TextSetting},
+	odometer::Frame,
+	spinner::Spinner,
+	stats::{GraphData, GraphRange, StatsPeriod},
+	text_entry::TextEntry,
+};
+
+/// Displays shorter than this get compact layouts, with smaller headings and fewer hints
+const SHORT_HEIGHT: u32 = 48;
+
+/// Font for the heading at the top of a screen, which is smaller on short displays
+fn heading_font(size: Size) -> &'static MonoFont<'static> {
+	if size.height < SHORT_HEIGHT {
+		&FONT_6X10
+	} else {
+		&FONT_10X20
+	}
+}
+
+/// Renders the count as large as it fits under its title, if there is one,
+/// along with progress towards the target and a banner if there's a highlight.
+/// While `roll` is part way through, the digits that changed are shown rolling into place
+pub fn render_count<D: DrawTarget<Color = BinaryColor>>(
+	display: &mut D,
+	title: Option<&str>,
+	count: Count,
+	roll: Option<Frame>,
+	config: &CounterConfig,
+	text_buf: &mut [u8],
+) -> Result<(), D::Error> {
+	let small_style = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+	let size = display.bounding_box().size;
+	let short = size.height < SHORT_HEIGHT;
+	let mut top = 0;
+	if let Some(title) = title {
+		let font = heading_font(size);
+		Text::with_baseline(
+			title,
+			Point::zero(),
+			MonoTextStyle::new(font, BinaryColor::On),
+			Baseline::Top,
+		)
+		.draw(display)?;
+		top = font.character_size.height + 2;
+	}
+	let mut bottom = size.height;
+	// Targets only apply when counting up
+	if config.target != 0 && config.direction == Direction::Up {
+		// Short displays only have room for a thin bar, which takes up the whole width
+		let bar = if short {
+			Rectangle::new(
+				Point::new(0, size.height as i32 - 4),
+				Size::new(size.width, 4),
+			)
+		} else {
+			let target = format_no_std::show(text_buf, format_args!("/{}", config.target))
+				.unwrap_or_default();
+			let target_width = target.len() as u32 * FONT_6X10.character_size.width;
+			Text::with_text_style(
+				target,
+				Point::new(size.width as i32, size.height as i32),
+				small_style,
+				TextStyleBuilder::new()
+					.alignment(Alignment::Right)
+					.baseline(Baseline::Bottom)
+					.build(),
+			)
+			.draw(display)?;
+			Rectangle::new(
+				Point::new(0, size.height as i32 - 9),
+				Size::new(size.width.saturating_sub(target_width + 2), 8),
+			)
+		};
+		bar.into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+			.draw(display)?;
+		let progress = count.clamp(0, config.target as Count) * bar.size.width as Count
+			/ config.target as Count;
+		Rectangle::new(bar.top_left, Size::new(progress as u32, bar.size.height))
+			.into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+			.draw(display)?;
+		bottom = (bar.top_left.y as u32).saturating_sub(2);
+	}
+	let (digits_buf, from_buf) = text_buf.split_at_mut(text_buf.len() / 2);
+	let digits = format_no_std::show(digits_buf, format_args!("{count}")).unwrap_or_default();
+	let roll = roll.map(|frame| {
+		let from =
+			format_no_std::show(from_buf, format_args!("{}", frame.from)).unwrap_or_default();
+		(from, count > frame.from, frame.progress)
+	});
+	render_digits(
+		display,
+		digits,
+		roll,
+		Rectangle::new(
+			Point::new(0, top as i32),
+			Size::new(size.width, bottom.saturating_sub(top)),
+		),
+	)?;
+	if let Some((highlight, _)) = current_highlight() {
+		render_banner(display, highlight, text_buf)?;
+	}
+	Ok(())
+}
+
+/// Segments lit for each digit, as bits in the order top, top right, bottom right, bottom,
+/// bottom left, top left and middle
+const SEGMENTS: [u8; 10] = [
+	0b0111111, 0b0000110, 0b1011011, 0b1001111, 0b1100110, 0b1101101, 0b1111101, 0b0000111,
+	0b1111111, 0b1101111,
+];
+/// Lit for a minus sign
+const MINUS: u8 = 0b1000000;
+
+/// Draws `text` as seven segment digits, as large as fits in `area` and centred in it.
+/// Anything other than digits and minus signs is left as a gap.
+/// While rolling, digits that differ from `from` slide in over them, upwards if `up`
+fn render_digits<D: DrawTarget<Color = BinaryColor>>(
+	display: &mut D,
+	text: &str,
+	roll: Option<(&str, bool, u32)>,
+	area: Rectangle,
+) -> Result<(), D::Error> {
+	// Sized for the longer of the two, so the digits stay still while rolling between lengths
+	let len = text.len().max(roll.map_or(0, |(from, _, _)| from.len()));
+	if len == 0 {
+		return Ok(());
+	}
+	let len = len as u32;
+	// Digits are half as wide as they are tall, with a quarter of their width between them
+	let width = (area.size.width * 4 / (5 * len - 1))
+		.min(area.size.height / 2)
+		.max(2);
+	let height = width * 2;
+	let gap = width / 4;
+	let total = len * width + (len - 1) * gap;
+	let top_left = area.top_left
+		+ Point::new(
+			(area.size.width - total) as i32 / 2,
+			(area.size.height - height) as i32 / 2,
+		);
+	// Right aligned, so the units line up while rolling between lengths
+	let char_at = |text: &str, i: u32| {
+		let i = (i + text.len() as u32).checked_sub(len)?;
+		text.chars().nth(i as usize)
+	};
+	for i in 0..len {
+		let cell = Rectangle::new(
+			top_left + Point::new((i * (width + gap)) as i32, 0),
+			Size::new(width, height),
+		);
+		let c = char_at(text, i);
+		match roll {
+			Some((from, up, progress)) if char_at(from, i) != c => {
+				// Both digits move by a digit and a gap over the roll, clipped to the digit's space
+				let distance = (height + gap) as i32;
+				let offset = distance * progress as i32 / 1000;
+				let (old, new) = if up {
+					(-offset, distance - offset)
+				} else {
+					(offset, offset - distance)
+				};
+				let mut clipped = display.clipped(&cell);
+				render_digit(&mut clipped, char_at(from, i), cell, old)?;
+				render_digit(&mut clipped, c, cell, new)?;
+			}
+			_ => render_digit(display, c, cell, 0)?,
+		}
+	}
+	Ok(())
+}
+
+/// Draws `c` as a seven segment digit filling `cell`, moved down by `offset`
+fn render_digit<D: DrawTarget<Color = BinaryColor>>(
+	display: &mut D,
+	c: Option<char>,
+	cell: Rectangle,
+	offset: i32,
+) -> Result<(), D::Error> {
+	let segments = match c {
+		Some(c @ '0'..='9') => SEGMENTS[c as usize - '0' as usize],
+		Some('-') => MINUS,
+		_ => return Ok(()),
+	};
+	let Size { width, height } = cell.size;
+	let thickness = (width / 5).max(1);
+	// Vertical segments overlap the middle one, so the joints are filled in
+	let half = (height + thickness) / 2;
+	let middle = (height - thickness) / 2;
+	let x = cell.top_left.x;
+	let y = cell.top_left.y + offset;
+	let right = x + (width - thickness) as i32;
+	let shapes = [
+		(Point::new(x, y), Size::new(width, thickness)),
+		(Point::new(right, y), Size::new(thickness, half)),
+		(
+			Point::new(right, y + middle as i32),
+			Size::new(thickness, height - middle),
+		),
+		(
+			Point::new(x, y + (height - thickness) as i32),
+			Size::new(width, thickness),
+		),
+		(
+			Point::new(x, y + middle as i32),
+			Size::new(thickness, height - middle),
+		),
+		(Point::new(x, y), Size::new(thickness, half)),
+		(
+			Point::new(x, y + middle as i32),
+			Size::new(width, thickness),
+		),
+	];
+	let style = PrimitiveStyle::with_fill(BinaryColor::On);
+	for (bit, (corner, size)) in shapes.into_iter().enumerate() {
+		if segments & (1 << bit) != 0 {
+			Rectangle::new(corner, size)
+				.into_styled(style)
+				.draw(display)?;
+		}
+	}
+	Ok(())
+}
+
+/// Draws an inverted banner across the middle of the display
+fn render_banner<D: DrawTarget<Color = BinaryColor>>(
+	display: &mut D,
+	highlight: Highlight,
+	text_buf: &mut [u8],
+) -> Result<(), D::Error> {
+	let size = display.bounding_box().size;
+	let banner = Rectangle::with_center(
+		Point::new(size.width as i32 / 2, size.height as i32 / 2),
+		Size::new(size.width, 28),
+	);
+	banner
+		.into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+		.draw(display)?;
+	let text = match highlight {
+		Highlight::Goal => Ok("Goal hit!"),
+		Highlight::Limit => Ok("Limit!"),
+		Highlight::Milestone(count) => format_no_std::show(text_buf, format_args!("{count} hit!")),
+	};
+	Text::with_text_style(
+		text.unwrap_or_default(),
+		banner.center(),
+		MonoTextStyle::new(&FONT_10X20, BinaryColor::Off),
+		TextStyleBuilder::new()
+			.alignment(Alignment::Center)
+			.baseline(Baseline::Middle)
+			.build(),
+	)
+	.draw(display)?;
+	Ok(())
+}
+
+/// Renders the tally for a period under its name
+pub fn render_stats<D: DrawTarget<Color = BinaryColor>>(
+	display: &mut D,
+	period: StatsPeriod,
+	text_buf: &mut [u8],
+) -> Result<(), D::Error> {
+	let size = display.bounding_box().size;
+	let font = heading_font(size);
+	let no_clock = period.needs_clock() && clock::now().is_none();
+	// Short displays have no room for a note, so it takes the heading's place
+	let heading = if no_clock && size.height < SHORT_HEIGHT {
+		"Clock not set"
+	} else {
+		period.into()
+	};
+	Text::with_baseline(
+		heading,
+		Point::zero(),
+		MonoTextStyle::new(font, BinaryColor::On),
+		Baseline::Top,
+	)
+	.draw(display)?;
+	Text::with_baseline(
+		format_no_std::show(text_buf, format_args!("{}", period.tally())).unwrap_or_default(),
+		Point::new(0, font.character_size.height as i32),
+		MonoTextStyle::new(&FONT_10X20, BinaryColor::On),
+		Baseline::Top,
+	)
+	.draw(display)?;
+	if no_clock && size.height >= SHORT_HEIGHT {
+		Text::with_baseline(
+			"Clock not set",
+			Point::new(0, size.height as i32),
+			MonoTextStyle::new(&FONT_6X10, BinaryColor::On),
+			Baseline::Bottom,
+		)
+		.draw(display)?;
+	}
+	Ok(())
+}
+
+/// Renders as many lines of diagnostics as fit, starting from `first`, under a title bar
+pub fn render_diagnostics<D: DrawTarget<Color = BinaryColor>>(
+	display: &mut D,
+	first: usize,
+	text_buf: &mut [u8],
+) -> Result<(), D::Error> {
+	let size = display.bounding_box().size;
+	Rectangle::new(Point::zero(), Size::new(size.width, TITLE_HEIGHT - 1))
+		.into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+		.draw(display)?;
+	Text::with_baseline(
+		"Diagnostics",
+		Point::new(1, 0),
+		MonoTextStyle::new(&FONT_6X10, BinaryColor::Off),
+		Baseline::Top,
+	)
+	.draw(display)?;
+	let line_height = FONT_6X10.character_size.height;
+	let rows = ((size.height - TITLE_HEIGHT) / line_height) as usize;
+	for (row, i) in (first..diagnostics::LINES).take(rows).enumerate() {
+		Text::with_baseline(
+			diagnostics::line(i, text_buf),
+			Point::new(0, (TITLE_HEIGHT + row as u32 * line_height) as i32),
+			MonoTextStyle::new(&FONT_6X10, BinaryColor::On),
+			Baseline::Top,
+		)
+		.draw(display)?;
+	}
+	Ok(())
+}
+
+/// Draws a message in an inverted box at the bottom of the display, over whatever's there
+pub fn render_toast<D: DrawTarget<Color = BinaryColor>>(
+	display: &mut D,
+	message: &str,
+) -> Result<(), D::Error> {
+	let size = display.bounding_box().size;
+	let text_size = FONT_6X10.character_size;
+	let toast = Rectangle::new(
+		Point::zero(),
+		Size::new(
+			(message.len() as u32 * text_size.width + 6).min(size.width),
+			text_size.height + 3,
+		),
+	);
+	let toast = Rectangle::new(
+		Point::new(
+			(size.width - toast.size.width) as i32 / 2,
+			(size.height - toast.size.height) as i32,
+		),
+		toast.size,
+	);
+	// A border in the background colour separates it from what's behind
+	toast
+		.offset(1)
+		.into_styled(PrimitiveStyle::with_fill(BinaryColor::Off))
+		.draw(display)?;
+	toast
+		.into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+		.draw(display)?;
+	Text::with_text_style(
+		message,
+		toast.center(),
+		MonoTextStyle::new(&FONT_6X10, BinaryColor::Off),
+		TextStyleBuilder::new()
+			.alignment(Alignment::Center)
+			.baseline(Baseline::Middle)
+			.build(),
+	)
+	.draw(display)?;
+	Ok(())
+}
+
+/// Renders the name and version of the firmware while everything's starting up
+pub fn render_splash<D: DrawTarget<Color = BinaryColor>>(
+	display: &mut D,
+	text_buf: &mut [u8],
+) -> Result<(), D::Error> {
+	let size = display.bounding_box().size;
+	let centre = Point::new(size.width as i32 / 2, size.height as i32 / 2);
+	let centred = TextStyleBuilder::new()
+		.alignment(Alignment::Center)
+		.baseline(Baseline::Bottom)
+		.build();
+	Text::with_text_style(
+		"Death Toll",
+		centre,
+		MonoTextStyle::new(heading_font(size), BinaryColor::On),
+		centred,
+	)
+	.draw(display)?;
+	Text::with_text_style(
+		format_no_std::show(
+			text_buf,
+			format_args!("v{} {}", diagnostics::VERSION, diagnostics::BUILD),
+		)
+		.unwrap_or_default(),
+		centre + Point::new(0, 2),
+		MonoTextStyle::new(&FONT_6X10, BinaryColor::On),
+		TextStyleBuilder::new()
+			.alignment(Alignment::Center)
+			.baseline(Baseline::Top)
+			.build(),
+	)
+	.draw(display)?;
+	Ok(())
+}
+
+/// Renders a bar chart of the count history, with the current rate and average above it
+pub fn render_graph<D: DrawTarget<Color = BinaryColor>>(
+	display: &mut D,
+	range: GraphRange,
+	text_buf: &mut [u8],
+) -> Result<(), D::Error> {
+	let data = GraphData::read(range);
+	let text_style = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+	let unit = match range {
+		GraphRange::Hours => "h",
+		GraphRange::Days => "d",
+	};
+	let header = if data.no_clock {
+		Ok("Clock not set")
+	} else {
+		format_no_std::show(
+			text_buf,
+			format_args!("{}/h  avg {:.1}/{unit}", data.rate, data.average),
+		)
+	};
+	Text::with_baseline(
+		header.unwrap_or_default(),
+		Point::zero(),
+		text_style,
+		Baseline::Top,
+	)
+	.draw(display)?;
+
+	let size = display.bounding_box().size;
+	let top = FONT_6X10.character_size.height + 2;
+	let graph_height = size.height.saturating_sub(top);
+	let counts = data.counts();
+	let bar_width = size.width / counts.len() as u32;
+	let max = counts.iter().copied().max().unwrap_or(0).max(1);
+	let bar_style = PrimitiveStyle::with_fill(BinaryColor::On);
+	for (i, &count) in counts.iter().enumerate() {
+		let height = (count as u64 * graph_height as u64 / max as u64) as u32;
+		// Always draw a baseline so empty slots are still visible
+		let height = height.max(1);
+		Rectangle::new(
+			Point::new((i as u32 * bar_width) as i32, (size.height - height) as i32),
+			// Leave a gap between bars
+			Size::new(bar_width.saturating_sub(1).max(1), height),
+		)
+		.into_styled(bar_style)
+		.draw(display)?;
+	}
+	Ok(())
+}
+
+/// How often a label that's too long to fit scrolls along by a character
+pub const MARQUEE_STEP: Duration = Duration::from_millis(300);
+/// How many steps a scrolling label waits for at each end
+const MARQUEE_PAUSE: u64 = 4;
+/// Height of the bar showing the menu's name
+const TITLE_HEIGHT: u32 = 11;
+/// Width of the scrollbar, including the gap to its left
+const SCROLLBAR_WIDTH: u32 = 4;
+
+/// Renders the current menu, or the spinner or confirmation if one is open,
+/// with a countdown if the menu is about to close itself.
+/// Returns whether anything is moving, so needs redrawing every [`MARQUEE_STEP`]
+pub fn render_menu<D: DrawTarget<Color = BinaryColor>>(
+	display: &mut D,
+	navigator: &Navigator,
+	text_buf: &mut [u8],
+) -> Result<bool, D::Error> {
+	let animating = if let Some(confirmation) = navigator.confirming() {
+		render_confirmation(display, confirmation, text_buf)?;
+		false
+	} else if let Some((setting, spinner)) = navigator.editing() {
+		render_spinner(display, setting, &spinner, text_buf)?;
+		false
+	} else if let Some((setting, entry)) = navigator.typing() {
+		render_text_entry(display, *setting, entry)?;
+		false
+	} else {
+		render_list(display, navigator, text_buf)?
+	};
+	if let Some(left) = navigator.time_left() {
+		render_countdown(display, left, text_buf)?;
+		return Ok(true);
+	}
+	Ok(animating)
+}
+
+/// Renders the current menu as a list under its name, scrolled to keep the selected item in view.
+/// Returns whether the selected label is scrolling
+fn render_list<D: DrawTarget<Color = BinaryColor>>(
+	display: &mut D,
+	navigator: &Navigator,
+	text_buf: &mut [u8],
+) -> Result<bool, D::Error> {
+	let size = display.bounding_box().size;
+	let menu = navigator.menu();
+	let title_style = MonoTextStyle::new(&FONT_6X10, BinaryColor::Off);
+	Rectangle::new(Point::zero(), Size::new(size.width, TITLE_HEIGHT - 1))
+		.into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+		.draw(display)?;
+	Text::with_baseline(menu.name, Point::new(1, 0), title_style, Baseline::Top).draw(display)?;
+	let items = menu.items;
+	if items.is_empty() {
+		return Ok(false);
+	}
+	let (font, row_height) = match read_display_config().menu_font {
+		MenuFont::Large => (&FONT_9X15, 17),
+		MenuFont::Small => (&FONT_6X10, 10),
+	};
+	let rows = ((size.height - TITLE_HEIGHT) / row_height).max(1) as usize;
+	// Keep the selected item in the middle, unless that would leave empty rows at either end
+	let index = navigator.index();
+	let first = index
+		.saturating_sub(rows / 2)
+		.min(items.len().saturating_sub(rows));
+	let scrolls = items.len() > rows;
+	let list_width = if scrolls {
+		size.width - SCROLLBAR_WIDTH
+	} else {
+		size.width
+	};
+	let check_width = row_height * 3 / 5;
+	let mut animating = false;
+	for (row, i) in (first..items.len().min(first + rows)).enumerate() {
+		let selected = i == index;
+		let (background, foreground) = if selected {
+			(BinaryColor::On, BinaryColor::Off)
+		} else {
+			(BinaryColor::Off, BinaryColor::On)
+		};
+		let top = (TITLE_HEIGHT + row as u32 * row_height) as i32;
+		if selected {
+			Rectangle::new(Point::new(0, top), Size::new(list_width, row_height))
+				.into_styled(PrimitiveStyle::with_fill(background))
+				.draw(display)?;
+		}
+		let active = navigator.is_active(i);
+		let text_width = if active {
+			list_width - check_width
+		} else {
+			list_width
+		};
+		let fits = (text_width / font.character_size.width) as usize;
+		let mut label = items[i].label(navigator.value(i), text_buf);
+		let overflow = label.chars().count().saturating_sub(fits);
+		if selected && overflow > 0 {
+			// Scroll along the label, waiting at the start and end so both can be read
+			let steps = navigator.selected_for().as_millis() / MARQUEE_STEP.as_millis();
+			let step = steps % (overflow as u64 + 2 * MARQUEE_PAUSE);
+			let offset = (step.saturating_sub(MARQUEE_PAUSE) as usize).min(overflow);
+			label = &label[label.char_indices().nth(offset).map_or(0, |(i, _)| i)..];
+			animating = true;
+		}
+		Text::with_baseline(
+			label,
+			Point::new(
+				0,
+				top + (row_height - font.character_size.height) as i32 / 2,
+			),
+			MonoTextStyle::new(font, foreground),
+			Baseline::Top,
+		)
+		.draw(display)?;
+		if active {
+			render_check_mark(
+				display,
+				Rectangle::new(
+					Point::new((list_width - check_width) as i32, top),
+					Size::new(check_width, row_height),
+				),
+				foreground,
+			)?;
+		}
+	}
+	if scrolls {
+		// The thumb's size and position show which part of the list is visible
+		let track_height = size.height - TITLE_HEIGHT;
+		let thumb_height = (track_height * rows as u32 / items.len() as u32).max(2);
+		let thumb_top = (track_height - thumb_height) * first as u32 / (items.len() - rows) as u32;
+		let x = (size.width - SCROLLBAR_WIDTH + 1) as i32;
+		Line::new(
+			Point::new(x + 1, TITLE_HEIGHT as i32),
+			Point::new(x + 1, size.height as i32 - 1),
+		)
+		.into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+		.draw(display)?;
+		Rectangle::new(
+			Point::new(x, (TITLE_HEIGHT + thumb_top) as i32),
+			Size::new(SCROLLBAR_WIDTH - 1, thumb_height),
+		)
+		.into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+		.draw(display)?;
+	}
+	Ok(animating)
+}
+
+/// Draws the seconds left before the menu closes in the top right corner, over whatever is there
+fn render_countdown<D: DrawTarget<Color = BinaryColor>>(
+	display: &mut D,
+	left: Duration,
+	text_buf: &mut [u8],
+) -> Result<(), D::Error> {
+	let text_style = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+	let size = display.bounding_box().size;
+	// Round up, so it closes as the countdown would reach 0
+	let seconds = left.as_millis().div_ceil(1000);
+	let text = format_no_std::show(text_buf, format_args!("{seconds}")).unwrap_or_default();
+	// Bordered, so it stands out against the title bar as well as a blank background
+	let width = text.len() as u32 * FONT_6X10.character_size.width + 4;
+	Rectangle::new(
+		Point::new((size.width - width) as i32, 0),
+		Size::new(width, 12),
+	)
+	.into_styled(
+		PrimitiveStyleBuilder::new()
+			.fill_color(BinaryColor::Off)
+			.stroke_color(BinaryColor::On)
+			.stroke_width(1)
+			.build(),
+	)
+	.draw(display)?;
+	Text::with_baseline(
+		text,
+		Point::new((size.width - width + 2) as i32, 1),
+		text_style,
+		Baseline::Top,
+	)
+	.draw(display)?;
+	Ok(())
+}
+
+/// Draws a tick filling `area`, clearing anything underneath it
+fn render_check_mark<D: DrawTarget<Color = BinaryColor>>(
+	display: &mut D,
+	area: Rectangle,
+	color: BinaryColor,
+) -> Result<(), D::Error> {
+	area.into_styled(PrimitiveStyle::with_fill(color.invert()))
+		.draw(display)?;
+	let Size { width, height } = area.size;
+	// The tick's corners, as proportions of the area
+	let point = |x: u32, y: u32| {
+		area.top_left + Point::new((x * width / 12) as i32, (y * height / 20) as i32)
+	};
+	let style = PrimitiveStyle::with_stroke(color, if height >= 15 { 2 } else { 1 });
+	Line::new(point(1, 10), point(4, 14))
+		.into_styled(style)
+		.draw(display)?;
+	Line::new(point(4, 14), point(10, 5))
+		.into_styled(style)
+		.draw(display)?;
+	Ok(())
+}
+
+/// Renders the value being edited under the setting's name, with hints for the buttons.
+/// When entering digits, all of them are shown with the one being changed inverted
+fn render_spinner<D: DrawTarget<Color = BinaryColor>>(
+	display: &mut D,
+	setting: NumberSetting,
+	spinner: &Spinner,
+	text_buf: &mut [u8],
+) -> Result<(), D::Error> {
+	let text_style = MonoTextStyle::new(&FONT_10X20, BinaryColor::On);
+	let small_style = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+	let size = display.bounding_box().size;
+	let (value_buf, hint_buf) = text_buf.split_at_mut(text_buf.len() / 2);
+	let font = heading_font(size);
+	Text::with_baseline(
+		setting.into(),
+		Point::zero(),
+		MonoTextStyle::new(font, BinaryColor::On),
+		Baseline::Top,
+	)
+	.draw(display)?;
+	let centred = TextStyleBuilder::new()
+		.alignment(Alignment::Center)
+		.baseline(Baseline::Middle)
+		.build();
+	// Centred in the space left under the heading, which on short displays has no room for the hint
+	let short = size.height < SHORT_HEIGHT;
+	let bottom = if short {
+		size.height
+	} else {
+		size.height - FONT_6X10.character_size.height
+	};
+	let middle = (font.character_size.height + bottom) as i32 / 2;
+	if spinner.by_digit {
+		let digits = spinner.digits();
+		let text = format_no_std::show(value_buf, format_args!("{:0digits$}", spinner.value))
+			.unwrap_or_default();
+		let char_size = FONT_10X20.character_size;
+		let left = (size.width as i32 - (digits as u32 * char_size.width) as i32) / 2;
+		let top = middle - char_size.height as i32 / 2;
+		Text::with_baseline(text, Point::new(left, top), text_style, Baseline::Top)
+			.draw(display)?;
+		let cursor = Point::new(
+			left + (spinner.cursor() as u32 * char_size.width) as i32,
+			top,
+		);
+		Rectangle::new(cursor, char_size)
+			.into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+			.draw(display)?;
+		Text::with_baseline(
+			text.get(spinner.cursor()..spinner.cursor() + 1)
+				.unwrap_or_default(),
+			cursor,
+			MonoTextStyle::new(&FONT_10X20, BinaryColor::Off),
+			Baseline::Top,
+		)
+		.draw(display)?;
+	} else {
+		Text::with_text_style(
+			setting.format(spinner.value, value_buf),
+			Point::new(size.width as i32 / 2, middle),
+			text_style,
+			centred,
+		)
+		.draw(display)?;
+	}
+	if short {
+		return Ok(());
+	}
+	// Shows what a press will do, and what holding does
+	let sign = if spinner.increasing { '+' } else { '-' };
+	let hint = if spinner.by_digit {
+		"press: +1  hold: next"
+	} else {
+		format_no_std::show(
+			hint_buf,
+			format_args!("{sign}{}/press  hold: flip", spinner.step),
+		)
+		.unwrap_or_default()
+	};
+	Text::with_baseline(
+		hint,
+		Point::new(0, size.height as i32),
+		small_style,
+		Baseline::Bottom,
+	)
+	.draw(display)?;
+	Ok(())
+}
+
+/// Renders the text being typed under the setting's name, with the character being changed inverted
+/// and hints for the buttons
+fn render_text_entry<D: DrawTarget<Color = BinaryColor>>(
+	display: &mut D,
+	setting: TextSetting,
+	entry: &TextEntry,
+) -> Result<(), D::Error> {
+	let text_style = MonoTextStyle::new(&FONT_10X20, BinaryColor::On);
+	let small_style = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+	let size = display.bounding_box().size;
+	let font = heading_font(size);
+	Text::with_baseline(
+		setting.into(),
+		Point::zero(),
+		MonoTextStyle::new(font, BinaryColor::On),
+		Baseline::Top,
+	)
+	.draw(display)?;
+	let top = font.character_size.height as i32;
+	// Scroll along once the cursor reaches the right edge
+	let char_width = FONT_10X20.character_size.width;
+	let visible = (size.width / char_width) as usize;
+	let first = (entry.cursor + 1).saturating_sub(visible);
+	let chars = entry.chars();
+	let end = chars.len().min(first + visible);
+	Text::with_baseline(
+		&chars[first..end],
+		Point::new(0, top),
+		text_style,
+		Baseline::Top,
+	)
+	.draw(display)?;
+	let cursor = Point::new(((entry.cursor - first) as u32 * char_width) as i32, top);
+	Rectangle::new(cursor, FONT_10X20.character_size)
+		.into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+		.draw(display)?;
+	Text::with_baseline(
+		&chars[entry.cursor..entry.cursor + 1],
+		cursor,
+		MonoTextStyle::new(&FONT_10X20, BinaryColor::Off),
+		Baseline::Top,
+	)
+	.draw(display)?;
+	// There's no room for hints on short displays
+	if size.height < SHORT_HEIGHT {
+		return Ok(());
+	}
+	Text::with_baseline(
+		"press: next letter",
+		Point::new(0, size.height as i32 - 10),
+		small_style,
+		Baseline::Bottom,
+	)
+	.draw(display)?;
+	Text::with_baseline(
+		"hold: move  long: save",
+		Point::new(0, size.height as i32),
+		small_style,
+		Baseline::Bottom,
+	)
+	.draw(display)?;
+	Ok(())
+}
+
+/// Renders the question asking to confirm an action, with hints for the buttons
+fn render_confirmation<D: DrawTarget<Color = BinaryColor>>(
+	display: &mut D,
+	confirmation: &Confirmation,
+	text_buf: &mut [u8],
+) -> Result<(), D::Error> {
+	let small_style = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+	let size = display.bounding_box().size;
+	Text::with_baseline(
+		confirmation.result.confirmation_prompt(text_buf),
+		Point::zero(),
+		MonoTextStyle::new(heading_font(size), BinaryColor::On),
+		Baseline::Top,
+	)
+	.draw(display)?;
+	Text::with_baseline(
+		"hold: yes  press: no",
+		Point::new(0, size.height as i32),
+		small_style,
+		Baseline::Bottom,
+	)
+	.draw(display)?;
+	Ok(())
+}
@@ -0,0 +1,148 @@
+//! Driver for 128x64 SH1106 panels in buffered graphics mode. These are mostly compatible with
+//! the SSD1306, but only support page addressing and have 132 columns with the panel in the middle
+
+use display_interface::{AsyncWriteOnlyDataCommand, DataFormat, DisplayError};
+use embedded_graphics::{
+	Pixel,
+	pixelcolor::BinaryColor,
+	prelude::{DrawTarget, OriginDimensions, Size},
+};
+use ssd1306::prelude::DisplayRotation;
+
+const WIDTH: usize = 128;
+const HEIGHT: usize = 64;
+const PAGES: usize = HEIGHT / 8;
+/// Columns of RAM to the left of the panel
+const COLUMN_OFFSET: u8 = 2;
+
+pub struct Sh1106<DI> {
+	interface: DI,
+	/// A byte per column of each page, with the top row in the lowest bit
+	buffer: [u8; WIDTH * PAGES],
+	/// Pages drawn to since the last flush, as bits
+	dirty: u8,
+	rotation: DisplayRotation,
+}
+impl<DI: AsyncWriteOnlyDataCommand> Sh1106<DI> {
+	pub fn new(interface: DI, rotation: DisplayRotation) -> Self {
+		Self {
+			interface,
+			buffer: [0; WIDTH * PAGES],
+			dirty: 0,
+			rotation,
+		}
+	}
+	async fn command(&mut self, bytes: &[u8]) -> Result<(), DisplayError> {
+		self.interface.send_commands(DataFormat::U8(bytes)).await
+	}
+	/// Sets the panel up and clears it
+	pub async fn init(&mut self) -> Result<(), DisplayError> {
+		self.command(&[
+			// Display off while it's set up
+			0xAE,
+			// Clock divide ratio and oscillator frequency
+			0xD5,
+			0x80,
+			// Multiplex ratio, one per row
+			0xA8,
+			HEIGHT as u8 - 1,
+			// No display offset, starting on the first line
+			0xD3,
+			0x00,
+			0x40,
+			// Charge pump on
+			0xAD,
+			0x8B,
+			// Alternative COM pin layout
+			0xDA,
+			0x12,
+			// Precharge period and VCOMH deselect level
+			0xD9,
+			0x22,
+			0xDB,
+			0x35,
+			// Show the RAM contents, not inverted
+			0xA4,
+			0xA6,
+		])
+		.await?;
+		self.set_rotation(self.rotation).await?;
+		self.clear_buffer();
+		self.flush().await?;
+		self.set_display_on(true).await
+	}
+	pub fn clear_buffer(&mut self) {
+		self.buffer.fill(0);
+		self.dirty = u8::MAX;
+	}
+	/// Sends the pages that have changed since the last flush
+	pub async fn flush(&mut self) -> Result<(), DisplayError> {
+		for page in 0..PAGES {
+			if self.dirty & (1 << page) == 0 {
+				continue;
+			}
+			self.command(&[
+				0xB0 | page as u8,
+				COLUMN_OFFSET & 0x0F,
+				0x10 | COLUMN_OFFSET >> 4,
+			])
+			.await?;
+			let row = &self.buffer[page * WIDTH..(page + 1) * WIDTH];
+			self.interface.send_data(DataFormat::U8(row)).await?;
+			self.dirty &= !(1 << page);
+		}
+		Ok(())
+	}
+	/// Contrast out of 255
+	pub async fn set_contrast(&mut self, contrast: u8) -> Result<(), DisplayError> {
+		self.command(&[0x81, contrast]).await
+	}
+	pub async fn set_display_on(&mut self, on: bool) -> Result<(), DisplayError> {
+		self.command(&[if on { 0xAF } else { 0xAE }]).await
+	}
+	pub async fn set_invert(&mut self, invert: bool) -> Result<(), DisplayError> {
+		self.command(&[if invert { 0xA7 } else { 0xA6 }]).await
+	}
+	/// Only supports turning it 180°, which flips the segment and COM scan directions
+	pub async fn set_rotation(&mut self, rotation: DisplayRotation) -> Result<(), DisplayError> {
+		self.rotation = rotation;
+		match rotation {
+			DisplayRotation::Rotate180 => self.command(&[0xA0, 0xC0]).await,
+			_ => self.command(&[0xA1, 0xC8]).await,
+		}
+	}
+}
+
+impl<DI> OriginDimensions for Sh1106<DI> {
+	fn size(&self) -> Size {
+		Size::new(WIDTH as u32, HEIGHT as u32)
+	}
+}
+
+impl<DI> DrawTarget for Sh1106<DI> {
+	type Color = BinaryColor;
+	type Error = DisplayError;
+
+	fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+	where
+		I: IntoIterator<Item = Pixel<Self::Color>>,
+	{
+		for Pixel(point, color) in pixels {
+			let (Ok(x), Ok(y)) = (usize::try_from(point.x), usize::try_from(point.y)) else {
+				continue;
+			};
+			if x >= WIDTH || y >= HEIGHT {
+				continue;
+			}
+			let byte = &mut self.buffer[(y / 8) * WIDTH + x];
+			let bit = 1 << (y % 8);
+			if color.is_on() {
+				*byte |= bit;
+			} else {
+				*byte &= !bit;
+			}
+			self.dirty |= 1 << (y / 8);
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,73 @@
+//! Widget for editing an integer value one step at a time, or one digit at a time for wide ranges
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Spinner {
+	pub value: u32,
+	pub min: u32,
+	pub max: u32,
+	/// Amount a press changes the value by. When entering digits, the place value of the digit being changed
+	pub step: u32,
+	/// Whether a press increases the value (rather than decreasing it)
+	pub increasing: bool,
+	/// Whether a press cycles the digit at `step`, for ranges too wide to step through
+	pub by_digit: bool,
+}
+impl Spinner {
+	pub fn new(value: u32, min: u32, max: u32, step: u32) -> Self {
+		let value = value.clamp(min, max);
+		Self {
+			value,
+			min,
+			max,
+			step,
+			// There's nowhere to go but down from the maximum
+			increasing: value < max,
+			by_digit: false,
+		}
+	}
+	/// Edits the value a digit at a time, starting from the highest digit `max` has
+	pub fn by_digits(value: u32, min: u32, max: u32) -> Self {
+		Self {
+			step: 10u32.pow(max.checked_ilog10().unwrap_or(0)),
+			by_digit: true,
+			..Self::new(value, min, max, 1)
+		}
+	}
+	/// Digits shown when entering digits, enough for `max`
+	pub fn digits(&self) -> usize {
+		self.max.checked_ilog10().unwrap_or(0) as usize + 1
+	}
+	/// Position of the digit being changed when entering digits, counting from the left
+	pub fn cursor(&self) -> usize {
+		self.digits() - 1 - self.step.checked_ilog10().unwrap_or(0) as usize
+	}
+	/// Moves the value one step in the current direction, stopping at the ends of the range.
+	/// When entering digits, moves the digit being changed on by one, going back to 0 after 9
+	/// or when the value would go past the maximum
+	pub fn spin(&mut self) {
+		self.value = if self.by_digit {
+			let digit = self.value / self.step % 10;
+			match self.value.checked_add(self.step) {
+				Some(value) if digit < 9 && value <= self.max => value,
+				_ => self.value - digit * self.step,
+			}
+			.max(self.min)
+		} else if self.increasing {
+			self.value.saturating_add(self.step).min(self.max)
+		} else {
+			self.value.saturating_sub(self.step).max(self.min)
+		};
+	}
+	/// Switches between increasing and decreasing the value.
+	/// When entering digits, moves on to the next digit, going back to the first after the last
+	pub fn reverse(&mut self) {
+		if self.by_digit {
+			self.step = match self.step / 10 {
+				0 => 10u32.pow(self.digits() as u32 - 1),
+				step => step,
+			};
+		} else {
+			self.increasing = !self.increasing;
+		}
+	}
+}
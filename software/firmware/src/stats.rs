@@ -0,0 +1,396 @@
+//! Keeps daily, weekly and monthly tallies of how much has been counted, alongside the lifetime total.
+//! Each tally is reset when the clock passes into a new period. Until the clock is set,
+//! the tallies carry on from the periods they were last counted in
+//!
+//! Also keeps a history of how the count has changed recently, for the graph screen.
+//! The last 30 days are saved, while the last hours are only kept until the device restarts
+
+use core::cell::{Cell, RefCell};
+
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use embassy_time::Instant;
+use strum::{IntoStaticStr, VariantArray};
+
+use crate::{
+	clock::{self, SECS_PER_DAY, days_since_epoch, month_index},
+	const_default::ConstDefault,
+	count::{Count, read_count},
+	record::{impl_field_for_enum, impl_record},
+};
+
+/// Hour of the day at which "today" rolls over to the next day
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, IntoStaticStr, VariantArray, PartialEq)]
+pub enum DayStart {
+	Midnight = 0,
+	#[strum(serialize = "3am")]
+	ThreeAm = 3,
+	#[strum(serialize = "6am")]
+	SixAm = 6,
+	#[strum(serialize = "9am")]
+	NineAm = 9,
+}
+impl ConstDefault for DayStart {
+	const DEFAULT: Self = Self::Midnight;
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, IntoStaticStr, VariantArray, PartialEq)]
+pub enum WeekStart {
+	Monday,
+	Sunday,
+}
+impl ConstDefault for WeekStart {
+	const DEFAULT: Self = Self::Monday;
+}
+
+/// Configures the boundaries at which the tallies roll over
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StatsConfig {
+	pub day_start: DayStart,
+	pub week_start: WeekStart,
+}
+impl ConstDefault for StatsConfig {
+	const DEFAULT: Self = Self {
+		day_start: DayStart::DEFAULT,
+		week_start: WeekStart::DEFAULT,
+	};
+}
+impl_field_for_enum!(DayStart, WeekStart);
+impl_record!(StatsConfig, version 1, [day_start, week_start]);
+
+impl StatsConfig {
+	/// The day, week and month that `time` (seconds since the unix epoch) falls in
+	fn periods(&self, time: u64) -> Periods {
+		let day = days_since_epoch(time.saturating_sub(self.day_start as u64 * 3600));
+		// 1st January 1970 was a Thursday
+		let week = match self.week_start {
+			WeekStart::Monday => (day + 3) / 7,
+			WeekStart::Sunday => (day + 4) / 7,
+		};
+		Periods {
+			day: day as u32,
+			week: week as u32,
+			month: month_index(day) as u32,
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Periods {
+	day: u32,
+	week: u32,
+	month: u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Tallies {
+	pub today: u32,
+	pub week: u32,
+	pub month: u32,
+	/// The periods that the tallies were counted in
+	periods: Periods,
+	/// Time the tallies were last updated, in seconds since the unix epoch
+	pub last_seen: u64,
+}
+impl ConstDefault for Tallies {
+	const DEFAULT: Self = Self {
+		today: 0,
+		week: 0,
+		month: 0,
+		periods: Periods {
+			day: 0,
+			week: 0,
+			month: 0,
+		},
+		last_seen: 0,
+	};
+}
+impl_record!(
+	Tallies,
+	version 1,
+	[today, week, month, periods.day, periods.week, periods.month, last_seen]
+);
+/// Ignores `last_seen`, so tallies are only equal if they hold the same counts for the same periods
+impl PartialEq for Tallies {
+	fn eq(&self, other: &Self) -> bool {
+		self.today == other.today
+			&& self.week == other.week
+			&& self.month == other.month
+			&& self.periods == other.periods
+	}
+}
+
+impl Tallies {
+	/// Resets any tally whose period has ended by `time`
+	fn roll_over(&mut self, config: &StatsConfig, time: u64) {
+		let periods = config.periods(time);
+		if periods.day != self.periods.day {
+			self.today = 0;
+		}
+		if periods.week != self.periods.week {
+			self.week = 0;
+		}
+		if periods.month != self.periods.month {
+			self.month = 0;
+		}
+		self.periods = periods;
+		self.last_seen = time;
+	}
+	fn add(&mut self, change: i64) {
+		self.today = apply_change(self.today, change);
+		self.week = apply_change(self.week, change);
+		self.month = apply_change(self.month, change);
+	}
+}
+
+// Blocking mutexes so that the tallies can be updated from the (synchronous) count functions
+pub static STATS_CONFIG: Mutex<CriticalSectionRawMutex, Cell<StatsConfig>> =
+	Mutex::new(Cell::new(StatsConfig::DEFAULT));
+pub static TALLIES: Mutex<CriticalSectionRawMutex, Cell<Tallies>> =
+	Mutex::new(Cell::new(Tallies::DEFAULT));
+
+pub fn read_stats_config() -> StatsConfig {
+	STATS_CONFIG.lock(|c| c.get())
+}
+
+pub fn write_stats_config(config: StatsConfig) {
+	STATS_CONFIG.lock(|c| c.set(config))
+}
+
+/// Applies `f` to the tallies after rolling them over to the current time, if the clock has been set
+fn update_tallies<R>(f: impl FnOnce(&mut Tallies) -> R) -> R {
+	let config = read_stats_config();
+	let now = clock::now();
+	TALLIES.lock(|t| {
+		let mut tallies = t.get();
+		if let Some(now) = now {
+			tallies.roll_over(&config, now);
+		}
+		let result = f(&mut tallies);
+		t.set(tallies);
+		result
+	})
+}
+
+/// Current tallies (rolled over to the current time, if it's known)
+pub fn read_tallies() -> Tallies {
+	update_tallies(|t| *t)
+}
+
+pub fn write_tallies(tallies: Tallies) {
+	TALLIES.lock(|t| t.set(tallies))
+}
+
+/// Adds `change` to `x`, saturating at the bounds of a `u32`
+fn apply_change(x: u32, change: i64) -> u32 {
+	let magnitude = u32::try_from(change.unsigned_abs()).unwrap_or(u32::MAX);
+	if change >= 0 {
+		x.saturating_add(magnitude)
+	} else {
+		x.saturating_sub(magnitude)
+	}
+}
+
+/// Records a change to the count in all of the tallies and the history
+pub fn record(change: i64) {
+	update_tallies(|t| t.add(change));
+	let uptime = Instant::now().as_secs();
+	let day_start = read_stats_config().day_start as u64 * 3600;
+	HISTORY.lock(|h| {
+		let mut h = h.borrow_mut();
+		h.minutes.add(uptime, change);
+		h.hours.add(uptime, change);
+		// Which day it is isn't known until the clock is set
+		if let Some(time) = clock::now() {
+			h.days.add(time.saturating_sub(day_start), change);
+		}
+	});
+}
+
+#[derive(Debug, Clone, Copy, IntoStaticStr, VariantArray, PartialEq)]
+pub enum StatsPeriod {
+	Today,
+	#[strum(serialize = "This Week")]
+	Week,
+	#[strum(serialize = "This Month")]
+	Month,
+	#[strum(serialize = "All Time")]
+	AllTime,
+}
+impl StatsPeriod {
+	/// The next period to show on the stats screen
+	pub fn next(self) -> Self {
+		let i = Self::VARIANTS.iter().position(|x| *x == self).unwrap_or(0);
+		Self::VARIANTS[(i + 1) % Self::VARIANTS.len()]
+	}
+	/// Whether the tally depends on the clock, so is only right once it's been set
+	pub fn needs_clock(self) -> bool {
+		self != Self::AllTime
+	}
+	pub fn tally(self) -> Count {
+		let tallies = read_tallies();
+		match self {
+			Self::Today => tallies.today.into(),
+			Self::Week => tallies.week.into(),
+			Self::Month => tallies.month.into(),
+			Self::AllTime => read_count(),
+		}
+	}
+}
+
+/// Changes to the count in each of the last `N` slots of time, where each slot is `SLOT` seconds long
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Buckets<const N: usize, const SLOT: u64> {
+	/// Oldest slot first
+	counts: [u32; N],
+	/// Index of the slot (since the unix epoch) that the last bucket covers
+	latest: u64,
+}
+impl<const N: usize, const SLOT: u64> Buckets<N, SLOT> {
+	const fn new() -> Self {
+		Self {
+			counts: [0; N],
+			latest: 0,
+		}
+	}
+	/// Moves the buckets along so the last bucket covers `time`
+	fn advance(&mut self, time: u64) {
+		let slot = time / SLOT;
+		// The clock can jump backwards when it's set. In that case keep adding to the latest bucket
+		if slot <= self.latest {
+			return;
+		}
+		let shift = slot - self.latest;
+		if shift >= N as u64 {
+			self.counts = [0; N];
+		} else {
+			self.counts.rotate_left(shift as usize);
+			self.counts[N - shift as usize..].fill(0);
+		}
+		self.latest = slot;
+	}
+	fn add(&mut self, time: u64, change: i64) {
+		self.advance(time);
+		self.counts[N - 1] = apply_change(self.counts[N - 1], change);
+	}
+	/// Index of the slot that the last bucket covers
+	pub fn latest(&self) -> u64 {
+		self.latest
+	}
+	/// Counts for each slot up to `time`, oldest first
+	fn counts_at(&self, time: u64) -> [u32; N] {
+		let mut buckets = *self;
+		buckets.advance(time);
+		buckets.counts
+	}
+}
+
+/// Counts for each of the last 30 days, which are saved (see `day_history`)
+pub type DayHistory = Buckets<30, SECS_PER_DAY>;
+impl ConstDefault for DayHistory {
+	const DEFAULT: Self = Self::new();
+}
+impl_record!(DayHistory, version 1, [counts, latest]);
+
+/// Minutes and hours are counted from boot, so they don't need the clock,
+/// but start again whenever the device restarts
+pub struct History {
+	minutes: Buckets<60, 60>,
+	hours: Buckets<24, 3600>,
+	days: Buckets<30, SECS_PER_DAY>,
+}
+pub static HISTORY: Mutex<CriticalSectionRawMutex, RefCell<History>> =
+	Mutex::new(RefCell::new(History {
+		minutes: Buckets::new(),
+		hours: Buckets::new(),
+		days: Buckets::new(),
+	}));
+
+/// The day history as it is now. It only needs saving once a new day has started (see `restore_day_history`)
+pub fn day_history() -> DayHistory {
+	HISTORY.lock(|h| h.borrow().days)
+}
+/// Carries on from the day history saved before the device restarted.
+/// Today's count isn't saved with it, but is the same as today's tally, so it's taken from there
+pub fn restore_day_history(mut saved: DayHistory) {
+	let tallies = TALLIES.lock(|t| t.get());
+	if tallies.periods.day as u64 == saved.latest {
+		saved.counts[29] = tallies.today;
+	}
+	HISTORY.lock(|h| {
+		let mut h = h.borrow_mut();
+		// Anything counted on a known day since boot is newer than what was saved, so is kept
+		if h.days == Buckets::new() {
+			h.days = saved;
+		}
+	});
+}
+
+#[derive(Debug, Clone, Copy, IntoStaticStr, VariantArray, PartialEq)]
+pub enum GraphRange {
+	#[strum(serialize = "Last 24h")]
+	Hours,
+	#[strum(serialize = "Last 30d")]
+	Days,
+}
+impl GraphRange {
+	pub fn next(self) -> Self {
+		match self {
+			Self::Hours => Self::Days,
+			Self::Days => Self::Hours,
+		}
+	}
+}
+
+/// Everything that's shown on the graph screen
+pub struct GraphData {
+	/// Count per bar, oldest first. Only the first `len` values are used
+	pub counts: [u32; 30],
+	pub len: usize,
+	/// Change in the count over the last 60 minutes
+	pub rate: u32,
+	/// Mean count per bar
+	pub average: f32,
+	/// Whether the bars need the clock, which hasn't been set, so are empty
+	pub no_clock: bool,
+}
+impl GraphData {
+	pub fn read(range: GraphRange) -> Self {
+		let uptime = Instant::now().as_secs();
+		let time = clock::now();
+		let day_start = read_stats_config().day_start as u64 * 3600;
+		HISTORY.lock(|h| {
+			let h = h.borrow();
+			let mut counts = [0; 30];
+			let len = match range {
+				GraphRange::Hours => {
+					counts[..24].copy_from_slice(&h.hours.counts_at(uptime));
+					24
+				}
+				GraphRange::Days => {
+					if let Some(time) = time {
+						counts.copy_from_slice(&h.days.counts_at(time.saturating_sub(day_start)));
+					}
+					30
+				}
+			};
+			// Summed into a `u64`, as the buckets could add up to more than a `u32` can hold
+			let total: u64 = counts[..len].iter().map(|&c| c as u64).sum();
+			Self {
+				counts,
+				len,
+				rate: h
+					.minutes
+					.counts_at(uptime)
+					.iter()
+					.fold(0, |rate: u32, &c| rate.saturating_add(c)),
+				average: total as f32 / len as f32,
+				no_clock: range == GraphRange::Days && time.is_none(),
+			}
+		})
+	}
+	pub fn counts(&self) -> &[u32] {
+		&self.counts[..self.len]
+	}
+}
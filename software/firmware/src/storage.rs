@@ -1,73 +1,367 @@
 use core::{marker::PhantomData, ops::Range};
 
-use embassy_embedded_hal::adapter::BlockingAsync;
-use esp_storage::FlashStorage;
+use embedded_storage_async::nor_flash::MultiwriteNorFlash;
 use sequential_storage::{
 	cache::KeyPointerCache,
+	erase_all,
 	map::{Value, fetch_item, store_item},
 };
+use strum::VariantArray;
+
+use crate::{
+	config::RgbConfig,
+	const_default::ConstDefault,
+	count::{CounterConfig, LegacyCount, StoredCount},
+	diagnostics::update_diagnostics,
+	display::DisplayConfig,
+	partitions::Partition,
+	stats::{DayHistory, StatsConfig, Tallies},
+	title::Title,
+	wear::{SaveConfig, Tracked, Wear},
+};
+
+/// Key each record is stored under. A key can't be reused for something else, as flash written by
+/// older firmware may still hold the old record. Two variants can't share a value, so keys can't collide
+#[derive(Debug, Clone, Copy, PartialEq, Eq, VariantArray)]
+#[repr(u8)]
+pub enum StorageKey {
+	/// The count, from when it was stored as a u32
+	LegacyCount = 0,
+	RgbConfig = 1,
+	Tallies = 2,
+	StatsConfig = 3,
+	CounterConfig = 4,
+	Count = 5,
+	DisplayConfig = 6,
+	Title = 7,
+	Wear = 8,
+	DayHistory = 9,
+	SaveConfig = 10,
+}
+/// Keys from here up are kept for the records of counters after the first (see `Storage::for_counter`)
+const FIRST_COUNTER_KEY: u8 = 0x80;
+/// Keys kept for each counter, leaving room for more records
+const KEYS_PER_COUNTER: u8 = 8;
+/// Most counters there's room to store records for
+pub const MAX_COUNTERS: u8 = (u8::MAX - FIRST_COUNTER_KEY) / KEYS_PER_COUNTER + 2;
+const _: () = {
+	let mut i = 0;
+	while i < StorageKey::VARIANTS.len() {
+		assert!(
+			(StorageKey::VARIANTS[i] as u8) < FIRST_COUNTER_KEY,
+			"Storage key is in the range kept for counters"
+		);
+		i += 1;
+	}
+	let mut i = 0;
+	while i < CounterRecord::VARIANTS.len() {
+		assert!(
+			(CounterRecord::VARIANTS[i] as u8) < KEYS_PER_COUNTER,
+			"Counter record doesn't fit in the keys kept for each counter"
+		);
+		i += 1;
+	}
+};
+
+/// Records stored once for each counter, by where they are in each counter's keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, VariantArray)]
+#[repr(u8)]
+pub enum CounterRecord {
+	Count = 0,
+	CounterConfig = 1,
+	Tallies = 2,
+	DayHistory = 3,
+}
+
+/// Key for `record` of counter `counter`, counting the first counter as 0.
+/// None for the first counter, whose records are kept under their `StorageKey`, and past the last counter
+const fn counter_key(counter: u8, record: CounterRecord) -> Option<u8> {
+	if counter == 0 || counter >= MAX_COUNTERS {
+		None
+	} else {
+		Some(FIRST_COUNTER_KEY + (counter - 1) * KEYS_PER_COUNTER + record as u8)
+	}
+}
+
+/// Stops the build if a key is in `keys` more than once
+const fn assert_unique(keys: &[u8]) {
+	let mut i = 0;
+	while i < keys.len() {
+		let mut j = i + 1;
+		while j < keys.len() {
+			assert!(
+				keys[i] != keys[j],
+				"Two records are stored under the same key"
+			);
+			j += 1;
+		}
+		i += 1;
+	}
+}
+
+/// A record saved to flash, under a key of its own.
+/// Only implemented through `impl_persisted!`, which checks that no two records share a key
+pub trait Persisted: for<'a> Value<'a> {
+	const KEY: StorageKey;
+}
+macro_rules! impl_persisted {
+	($($x:ty => $key:ident),* $(,)?) => {
+		$(impl Persisted for $x {
+			const KEY: StorageKey = StorageKey::$key;
+		})*
+		const _: () = assert_unique(&[$(StorageKey::$key as u8),*]);
+	};
+}
+impl_persisted!(
+	// Only ever read, to carry the count over from older firmware
+	LegacyCount => LegacyCount,
+	RgbConfig => RgbConfig,
+	Tallies => Tallies,
+	StatsConfig => StatsConfig,
+	CounterConfig => CounterConfig,
+	StoredCount => Count,
+	DisplayConfig => DisplayConfig,
+	Title => Title,
+	Wear => Wear,
+	DayHistory => DayHistory,
+	SaveConfig => SaveConfig,
+);
+
+/// A record stored once for each counter.
+/// Only implemented through `impl_per_counter!`, which checks that no two records share a place
+pub trait PerCounter: Persisted {
+	const RECORD: CounterRecord;
+}
+macro_rules! impl_per_counter {
+	($($x:ty => $record:ident),* $(,)?) => {
+		$(impl PerCounter for $x {
+			const RECORD: CounterRecord = CounterRecord::$record;
+		})*
+		const _: () = assert_unique(&[$(CounterRecord::$record as u8),*]);
+	};
+}
+impl_per_counter!(
+	StoredCount => Count,
+	CounterConfig => CounterConfig,
+	Tallies => Tallies,
+	DayHistory => DayHistory,
+);
+
+/// Why reading or writing the stored data failed, where `E` is the flash's own error
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StorageError<E> {
+	/// The flash itself couldn't be read, written or erased
+	Flash(E),
+	/// There's no room left in the region, even after clearing out old items
+	Full,
+	/// The region doesn't hold valid data, so needs erasing before it can be used
+	Corrupted,
+	/// The item doesn't fit in the buffer, or couldn't be converted to bytes
+	Item,
+}
+impl<E> StorageError<E> {
+	/// Whether erasing the region (losing what's stored) is the only way to recover
+	pub fn needs_erase(&self) -> bool {
+		matches!(self, Self::Full | Self::Corrupted)
+	}
+}
+impl<E> From<sequential_storage::Error<E>> for StorageError<E> {
+	fn from(e: sequential_storage::Error<E>) -> Self {
+		match e {
+			sequential_storage::Error::Storage { value } => Self::Flash(value),
+			sequential_storage::Error::FullStorage => Self::Full,
+			sequential_storage::Error::Corrupted { .. } => Self::Corrupted,
+			_ => Self::Item,
+		}
+	}
+}
+
+/// Bytes to read or write an item through, which must fit the largest record and its key
+const BUFFER_SIZE: usize = 160;
 
 /// Storage for a single type, T
-pub struct Storage<T: for<'a> Value<'a>> {
-	data_buffer: [u8; 128],
+pub struct Storage<T: Persisted> {
+	data_buffer: [u8; BUFFER_SIZE],
 	search_key: u8,
 	phantom: PhantomData<T>,
 }
-const fn page_count() -> usize {
-	const CAPACITY: usize = 4194304;
-	CAPACITY / FlashStorage::SECTOR_SIZE as usize
+/// Size of the region in bytes. It takes the start of its partition, leaving the rest free to store something else
+pub const REGION_SIZE: u32 = 0x3000;
+/// Size of the pages of the ESP32-C3's flash, each of which is erased separately
+pub const PAGE_SIZE: u32 = 4096;
+/// Pages in the region
+pub const REGION_PAGES: usize = (REGION_SIZE / PAGE_SIZE) as usize;
+
+/// Why the region can't be put in the partition found for it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RegionError {
+	/// The partition is smaller than the region
+	TooSmall,
+	/// The partition doesn't start at the beginning of a page
+	NotAligned,
+	/// The partition goes past the end of the flash
+	OutsideFlash,
 }
-/// Region of flash where the data will be stored. Includes a cache for this flash range
-pub struct FlashRegion {
-	flash: BlockingAsync<FlashStorage>,
-	cache: KeyPointerCache<{ page_count() }, u8, 2>,
+
+/// Region of flash where the data will be stored. Includes a cache for this flash range.
+/// On the device `F` is the ESP32-C3's flash, but it can be anything that can be written more than once
+pub struct FlashRegion<F: MultiwriteNorFlash> {
+	flash: F,
+	cache: KeyPointerCache<REGION_PAGES, u8, 2>,
 	flash_range: Range<u32>,
+	/// Times the region has been erased since boot, so users can tell they need to store everything again
+	erases: u32,
+	/// Erases and writes of each page, counted as they happen
+	wear: Wear,
+	/// Bytes used when `used` was last worked out, and the wear then. Nothing can change without a
+	/// write or erase, which would have changed the wear
+	usage: Option<(Wear, u32)>,
 }
-impl FlashRegion {
-	pub fn new(flash: FlashStorage, flash_range: Range<u32>) -> Self {
-		let flash = BlockingAsync::new(flash);
+impl<F: MultiwriteNorFlash> FlashRegion<F> {
+	/// Size of the region in bytes
+	pub fn size(&self) -> u32 {
+		self.flash_range.len() as u32
+	}
+	/// Bytes of the region that have been written to. Items are written to each page from the start,
+	/// so everything after the last byte that isn't erased (0xFF) is free.
+	/// Only read from flash again once something's been written or erased
+	pub async fn used(&mut self) -> Result<u32, StorageError<F::Error>> {
+		if let Some((wear, used)) = self.usage
+			&& wear == self.wear
+		{
+			return Ok(used);
+		}
+		let mut used = 0;
+		let mut chunk = [0u8; 256];
+		for page in self.flash_range.clone().step_by(F::ERASE_SIZE) {
+			let mut page_used = 0;
+			for offset in (0..F::ERASE_SIZE as u32).step_by(chunk.len()) {
+				self.flash
+					.read(page + offset, &mut chunk)
+					.await
+					.map_err(StorageError::Flash)?;
+				if let Some(last) = chunk.iter().rposition(|&b| b != 0xFF) {
+					page_used = offset + last as u32 + 1;
+				}
+			}
+			used += page_used;
+		}
+		self.usage = Some((self.wear, used));
+		Ok(used)
+	}
+	/// The region starting at `start`, which must be at the beginning of a page
+	pub fn new(flash: F, start: u32) -> Self {
 		let cache = KeyPointerCache::new();
 		Self {
 			flash,
-			flash_range,
+			flash_range: start..start + REGION_SIZE,
 			cache,
+			erases: 0,
+			wear: Wear::DEFAULT,
+			usage: None,
+		}
+	}
+	/// The region at the start of `partition`, as long as it fits without touching anything else
+	pub fn in_partition(flash: F, partition: Partition) -> Result<Self, RegionError> {
+		if partition.size < REGION_SIZE {
+			Err(RegionError::TooSmall)
+		} else if !partition.offset.is_multiple_of(F::ERASE_SIZE as u32) {
+			Err(RegionError::NotAligned)
+		} else if partition.range().end as usize > flash.capacity() {
+			Err(RegionError::OutsideFlash)
+		} else {
+			Ok(Self::new(flash, partition.offset))
+		}
+	}
+	/// Gives the flash back, such as to start again with an empty cache as if after a reboot
+	pub fn into_flash(self) -> F {
+		self.flash
+	}
+	/// Erases everything stored in the region
+	pub async fn erase(&mut self) -> Result<(), StorageError<F::Error>> {
+		let mut flash = Tracked::new(&mut self.flash, &mut self.wear, self.flash_range.start);
+		erase_all(&mut flash, self.flash_range.clone()).await?;
+		// Whatever was cached points to items that no longer exist
+		self.cache = KeyPointerCache::new();
+		self.erases += 1;
+		Ok(())
+	}
+	/// Times the region has been erased since boot
+	pub fn erases(&self) -> u32 {
+		self.erases
+	}
+	/// How worn each page is, including what was stored from before the last boot (see `resume_wear`)
+	pub fn wear(&self) -> Wear {
+		self.wear
+	}
+	/// Carries on counting from the wear stored before the last boot
+	pub fn resume_wear(&mut self, stored: &Wear) {
+		self.wear.add(stored);
+	}
+}
+impl<T: Persisted> Default for Storage<T> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+impl<T: PerCounter> Storage<T> {
+	/// Storage for counter `counter`'s copy of `T`, counting the first counter as 0. The first counter's
+	/// is under the key registered for `T`, so it's the one older firmware stored.
+	/// None if there isn't room for that many counters
+	pub fn for_counter(counter: u8) -> Option<Self> {
+		match counter {
+			0 => Some(Self::new()),
+			_ => counter_key(counter, T::RECORD).map(Self::with_key),
 		}
 	}
 }
-impl<T: for<'a> Value<'a>> Storage<T> {
-	/// MUST ensure that `search_key` is unique for this type
-	pub fn new(search_key: u8) -> Self {
-		let data_buffer = [0; 128];
+impl<T: Persisted> Storage<T> {
+	/// Storage under the key registered for `T` (see `StorageKey`)
+	pub fn new() -> Self {
+		Self::with_key(T::KEY as u8)
+	}
+	fn with_key(search_key: u8) -> Self {
+		let data_buffer = [0; BUFFER_SIZE];
 		Self {
 			search_key,
 			data_buffer,
 			phantom: PhantomData,
 		}
 	}
-	pub async fn fetch(&mut self, flash: &mut FlashRegion) -> Option<T> {
-		fetch_item::<u8, T, _>(
-			&mut flash.flash,
+	pub async fn fetch<F: MultiwriteNorFlash>(
+		&mut self,
+		flash: &mut FlashRegion<F>,
+	) -> Result<Option<T>, StorageError<F::Error>> {
+		match fetch_item::<u8, T, _>(
+			&mut Tracked::new(&mut flash.flash, &mut flash.wear, flash.flash_range.start),
 			flash.flash_range.clone(),
 			&mut flash.cache,
 			&mut self.data_buffer,
 			&self.search_key,
 		)
 		.await
-		.unwrap()
+		{
+			Ok(value) => Ok(value),
+			// Saved in a format or version this firmware can't read (see `record`), so start from the default
+			Err(sequential_storage::Error::SerializationError(_)) => Ok(None),
+			Err(e) => Err(e.into()),
+		}
 	}
-	pub async fn write(
+	pub async fn write<F: MultiwriteNorFlash>(
 		&mut self,
 		value: &T,
-		flash: &mut FlashRegion,
-	) -> Result<(), sequential_storage::Error<esp_storage::FlashStorageError>> {
+		flash: &mut FlashRegion<F>,
+	) -> Result<(), StorageError<F::Error>> {
 		store_item::<u8, T, _>(
-			&mut flash.flash,
+			&mut Tracked::new(&mut flash.flash, &mut flash.wear, flash.flash_range.start),
 			flash.flash_range.clone(),
 			&mut flash.cache,
 			&mut self.data_buffer,
 			&self.search_key,
 			value,
 		)
-		.await
+		.await?;
+		update_diagnostics(|d| d.storage_writes += 1);
+		Ok(())
 	}
 }
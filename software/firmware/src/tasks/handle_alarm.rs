@@ -0,0 +1,25 @@
+use embassy_time::Timer;
+use esp_hal::{
+	gpio::{self, OutputConfig},
+	peripherals::GPIO4,
+};
+
+use crate::{
+	alarm::{ALARM_RAISED, alarm_active},
+	count::read_counter_config,
+};
+
+/// Beeps the buzzer while the alarm is raised (if the buzzer is enabled)
+#[embassy_executor::task]
+pub async fn handle_alarm(buzzer_pin: GPIO4<'static>) {
+	let mut buzzer = gpio::Output::new(buzzer_pin, gpio::Level::Low, OutputConfig::default());
+	loop {
+		ALARM_RAISED.wait().await;
+		while alarm_active() && read_counter_config().buzzer {
+			buzzer.set_high();
+			Timer::after_millis(200).await;
+			buzzer.set_low();
+			Timer::after_millis(300).await;
+		}
+	}
+}
@@ -1,14 +1,22 @@
+#[cfg(feature = "device")]
 use core::pin;
 
 use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+#[cfg(feature = "device")]
 use embassy_time::{Duration, Instant, Timer};
+#[cfg(feature = "device")]
 use esp_hal::{
 	gpio::{self, InputConfig, OutputConfig},
 	peripherals::{GPIO2, GPIO3},
 };
+#[cfg(feature = "device")]
 use futures::future::select;
+#[cfg(feature = "device")]
 use smart_leds::RGB8;
 
+#[cfg(feature = "device")]
+use crate::diagnostics::update_diagnostics;
+#[cfg(feature = "device")]
 use crate::tasks::handle_neopixel::{RGB_CONFIG, RgbMode};
 
 pub static BUTTON_STATE: Signal<CriticalSectionRawMutex, ButtonEvent> = Signal::new();
@@ -18,6 +26,7 @@ pub enum ButtonEvent {
 	HoldHalfSecond,
 	HoldFullSecond,
 }
+#[cfg(feature = "device")]
 #[embassy_executor::task]
 pub async fn handle_button(led_pin: GPIO2<'static>, button_pin: GPIO3<'static>) {
 	let mut led = gpio::Output::new(led_pin, gpio::Level::Low, OutputConfig::default());
@@ -71,6 +80,10 @@ pub async fn handle_button(led_pin: GPIO2<'static>, button_pin: GPIO3<'static>)
 			continue;
 		};
 		esp_println::dbg!("Button Press: ", &button_event);
+		// The previous event hasn't been handled yet, so it's about to be lost
+		if BUTTON_STATE.signaled() {
+			update_diagnostics(|d| d.button_drops += 1);
+		}
 		BUTTON_STATE.signal(button_event);
 	}
 }
@@ -0,0 +1,94 @@
+//! Reads commands typed over the USB serial console
+
+#[cfg(feature = "device")]
+use embedded_io_async::Read;
+#[cfg(feature = "device")]
+use esp_hal::{peripherals::USB_DEVICE, usb_serial_jtag::UsbSerialJtag};
+
+use crate::{
+	clock,
+	display::REDRAW,
+	println,
+	tasks::handle_storage::CONFIG_UPDATED,
+	title::{TITLE_LEN, Title, read_title, write_title},
+};
+
+/// Longest line that can be typed, which is plenty for any command
+#[cfg(feature = "device")]
+const LINE_LEN: usize = 64;
+
+/// Runs a single line typed into the console
+pub fn run(line: &str) {
+	let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+	match (command, argument.trim()) {
+		("title", "") => println!("Title: {}", read_title().as_str()),
+		("title", text) => {
+			let title = Title::new(text);
+			write_title(title);
+			CONFIG_UPDATED.signal(());
+			REDRAW.signal(());
+			println!("Title set to {}", title.as_str());
+		}
+		("time", "") => match clock::now() {
+			Some(time) => println!("Time: {time}"),
+			None => println!("Time not set"),
+		},
+		("time", secs) => match secs.parse() {
+			Ok(secs) if clock::set_time(secs) => {
+				REDRAW.signal(());
+				println!("Time set to {secs}");
+			}
+			Ok(_) => println!(
+				"Time should be between when the firmware was built ({}) and 2100 ({})",
+				clock::BUILD_TIME,
+				clock::LATEST_TIME
+			),
+			Err(_) => println!("Time should be in seconds since 1970, such as from `date +%s`"),
+		},
+		_ => {
+			println!("Commands:");
+			println!("  title         shows the title");
+			println!("  title <text>  sets the title, up to {TITLE_LEN} bytes");
+			println!("  time          shows the time, in seconds since 1970");
+			println!("  time <secs>   sets the time, which is lost when powered off");
+		}
+	}
+}
+
+#[cfg(feature = "device")]
+#[embassy_executor::task]
+pub async fn handle_console(usb: USB_DEVICE<'static>) {
+	let (mut rx, _tx) = UsbSerialJtag::new(usb).into_async().split();
+	let mut line = [0u8; LINE_LEN];
+	let mut len = 0;
+	// Set when the line doesn't fit, so the rest of it is ignored rather than run cut short
+	let mut overflowed = false;
+	let mut chunk = [0u8; 16];
+	loop {
+		let Ok(read) = rx.read(&mut chunk).await else {
+			continue;
+		};
+		for &byte in &chunk[..read] {
+			match byte {
+				b'\r' | b'\n' => {
+					if overflowed {
+						println!("Line too long");
+					} else if let Ok(text) = core::str::from_utf8(&line[..len]) {
+						if !text.trim().is_empty() {
+							run(text.trim());
+						}
+					} else {
+						println!("Line isn't valid UTF-8");
+					}
+					len = 0;
+					overflowed = false;
+				}
+				_ if len < LINE_LEN => {
+					line[len] = byte;
+					len += 1;
+				}
+				_ => overflowed = true,
+			}
+		}
+	}
+}
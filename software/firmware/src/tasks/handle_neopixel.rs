@@ -1,24 +1,35 @@
+use core::ops::RangeInclusive;
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::mutex::Mutex;
-use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, watch::Watch};
-use embassy_time::Instant;
+#[cfg(feature = "device")]
+use embassy_time::{Duration, Instant};
+#[cfg(feature = "device")]
 use esp_hal::{
 	Async,
 	peripherals::{GPIO5, RNG},
 	rmt::ChannelCreator,
 	rng::Rng,
 };
+#[cfg(feature = "device")]
 use esp_hal_smartled::{SmartLedsAdapterAsync, smart_led_buffer};
+use smart_leds::RGB8;
+#[cfg(feature = "device")]
 use smart_leds::{
-	RGB8, SmartLedsWriteAsync as _, brightness, gamma,
+	SmartLedsWriteAsync as _, brightness, gamma,
 	hsv::{Hsv, hsv2rgb},
 };
 use strum::IntoStaticStr;
 
+#[cfg(feature = "device")]
 use crate::{
-	config::RgbConfig,
-	const_default::ConstDefault,
+	alarm::alarm_active,
+	count::COUNT,
+	display::is_headless,
+	highlight::{Highlight, current_highlight},
 	maths::{FibonacciWrapped, sin},
 };
+use crate::{config::RgbConfig, const_default::ConstDefault};
 
 #[derive(Clone, Debug, IntoStaticStr, PartialEq)]
 pub enum RgbMode {
@@ -32,8 +43,16 @@ impl ConstDefault for RgbMode {
 	const DEFAULT: Self = Self::SineCycle(0.01);
 }
 
+/// Rates that the effects and the rate modifier are kept within. Some effects divide by them,
+/// so they can't be 0
+pub const RGB_RATES: RangeInclusive<u32> = 1..=60;
+
+/// How long the LED flashes for when the count changes without a display
+#[cfg(feature = "device")]
+const COUNT_FLASH: Duration = Duration::from_millis(150);
+
 pub static RGB_CONFIG: Mutex<CriticalSectionRawMutex, RgbConfig> = Mutex::new(RgbConfig::DEFAULT);
-pub static RGB_CONFIG_UPDATED: Watch<CriticalSectionRawMutex, u8, 4> = Watch::new();
+#[cfg(feature = "device")]
 #[embassy_executor::task]
 pub async fn handle_neopixel(
 	rmt_channel: ChannelCreator<Async, 0>,
@@ -44,57 +63,93 @@ pub async fn handle_neopixel(
 	let mut rng = Rng::new(rng);
 	let mut fib = FibonacciWrapped::new();
 	let mut prev_colour = RGB8::new(0, 0, 0);
+	let mut prev_count = COUNT.try_get();
+	let mut flash_until = Instant::from_ticks(0);
 	loop {
 		let config = RGB_CONFIG.lock().await.clone();
-		let rate_multiplier = config.rgb_rate_modifier as u8;
-		let colour = match config.rgb_mode {
-			RgbMode::SineCycle(rate) => {
-				let time = Instant::now().as_micros() as f64 / 1E6;
-				let colour = Hsv {
-					hue: (sin(time * (rate as f64 * rate_multiplier as f64)) * 255.0) as u8,
-					sat: 255,
-					val: 255,
-				};
-				hsv2rgb(colour)
+		let rate_multiplier = config.rgb_rate_modifier;
+		let count = COUNT.try_get();
+		if count != prev_count {
+			prev_count = count;
+			if is_headless() {
+				flash_until = Instant::now() + COUNT_FLASH;
 			}
-			RgbMode::Continuous(rate) => {
-				let time = Instant::now().as_micros() as f64 / 1E6;
-				let colour = Hsv {
-					hue: ((time * rate as f64 * rate_multiplier as f64) as u64 % 255) as u8,
-					sat: 255,
-					val: 255,
-				};
-				hsv2rgb(colour)
+		}
+		let colour = if alarm_active() {
+			// Flash red until the alarm is acknowledged
+			if (Instant::now().as_millis() / 250) % 2 == 0 {
+				RGB8::new(255, 0, 0)
+			} else {
+				RGB8::new(0, 0, 0)
 			}
-			RgbMode::Random(rate) => {
-				let time = Instant::now().as_millis() as u32;
-				if time % (5000 / (rate * rate_multiplier as u32)) == 0 {
+		} else if Instant::now() < flash_until {
+			// Without a display, this is the only sign that a press was counted
+			RGB8::new(255, 255, 255)
+		} else if let Some((highlight, _)) = current_highlight() {
+			match highlight {
+				Highlight::Milestone(_) | Highlight::Goal => {
+					// Flash through the colours quickly
+					let time = Instant::now().as_millis();
 					let colour = Hsv {
-						hue: (rng.random() / 257) as u8,
+						hue: (time / 4) as u8,
 						sat: 255,
-						val: 255,
+						val: if (time / 150) % 2 == 0 { 255 } else { 0 },
 					};
 					hsv2rgb(colour)
-				} else {
-					embassy_futures::yield_now().await;
-					continue;
 				}
+				// Orange
+				Highlight::Limit => RGB8::new(255, 80, 0),
 			}
-			RgbMode::Fibonacci(rate) => {
-				let time = Instant::now().as_millis() as u32;
-				if time % (5000 / (rate * rate_multiplier as u32)) == 0 {
+		} else {
+			match config.rgb_mode {
+				RgbMode::SineCycle(rate) => {
+					let time = Instant::now().as_micros() as f64 / 1E6;
 					let colour = Hsv {
-						hue: fib.next(),
+						hue: (sin(time * (rate as f64 * rate_multiplier as f64)) * 255.0) as u8,
 						sat: 255,
 						val: 255,
 					};
 					hsv2rgb(colour)
-				} else {
-					embassy_futures::yield_now().await;
-					continue;
 				}
+				RgbMode::Continuous(rate) => {
+					let time = Instant::now().as_micros() as f64 / 1E6;
+					let colour = Hsv {
+						hue: ((time * rate as f64 * rate_multiplier as f64) as u64 % 255) as u8,
+						sat: 255,
+						val: 255,
+					};
+					hsv2rgb(colour)
+				}
+				RgbMode::Random(rate) => {
+					let time = Instant::now().as_millis() as u32;
+					if time % change_period(rate, rate_multiplier) == 0 {
+						let colour = Hsv {
+							hue: (rng.random() / 257) as u8,
+							sat: 255,
+							val: 255,
+						};
+						hsv2rgb(colour)
+					} else {
+						embassy_futures::yield_now().await;
+						continue;
+					}
+				}
+				RgbMode::Fibonacci(rate) => {
+					let time = Instant::now().as_millis() as u32;
+					if time % change_period(rate, rate_multiplier) == 0 {
+						let colour = Hsv {
+							hue: fib.next(),
+							sat: 255,
+							val: 255,
+						};
+						hsv2rgb(colour)
+					} else {
+						embassy_futures::yield_now().await;
+						continue;
+					}
+				}
+				RgbMode::Static(colour) => colour,
 			}
-			RgbMode::Static(colour) => colour,
 		};
 		// Diff the colour (don't write to neopixel if the colour is the same as the previous colour)
 		if prev_colour == colour {
@@ -102,10 +157,17 @@ pub async fn handle_neopixel(
 			continue;
 		}
 		prev_colour = colour;
-		let level = config.rgb_brightness as u8;
+		let level = config.rgb_brightness;
 		neopixel
 			.write(brightness(gamma([colour].into_iter()), level))
 			.await
 			.unwrap();
 	}
 }
+
+/// Milliseconds between colours for the effects that jump between them
+#[cfg(feature = "device")]
+fn change_period(rate: u32, rate_multiplier: u8) -> u32 {
+	// Kept above 0, even if the rates are out of range
+	(5000 / rate.saturating_mul(rate_multiplier as u32).max(1)).max(1)
+}
@@ -1,94 +1,712 @@
-//! Handles storing the config and count to the flash memory of the ESP32-C3
+//! Handles storing the config, title, count and tallies to the flash memory of the ESP32-C3
 
-use core::pin::pin;
+use core::{fmt::Debug, pin::pin};
 
+#[cfg(feature = "device")]
+use embassy_embedded_hal::adapter::BlockingAsync;
 use embassy_futures::join::join;
-use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex};
-use embassy_time::Timer;
-use esp_println::println;
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex, signal::Signal};
+use embassy_time::{Duration, Instant, Timer};
+use embedded_storage_async::nor_flash::MultiwriteNorFlash;
+#[cfg(feature = "device")]
 use esp_storage::FlashStorage;
 use futures::future::{Either, select};
 
+#[cfg(feature = "device")]
+use crate::partitions::{self, DATA, NVS, TableError};
 use crate::{
 	config::RgbConfig,
-	count::COUNT,
-	storage::{FlashRegion, Storage},
-	tasks::handle_neopixel::{RGB_CONFIG, RGB_CONFIG_UPDATED},
+	count::{
+		COUNT, Count, CounterConfig, LegacyCount, StoredCount, read_counter_config,
+		write_counter_config,
+	},
+	diagnostics::update_diagnostics,
+	display::{DisplayConfig, REDRAW, read_display_config, write_display_config},
+	println,
+	stats::{
+		DayHistory, StatsConfig, Tallies, day_history, read_stats_config, read_tallies,
+		restore_day_history, write_stats_config, write_tallies,
+	},
+	storage::{FlashRegion, Persisted, Storage, StorageError},
+	tasks::handle_neopixel::RGB_CONFIG,
+	title::{Title, read_title, write_title},
+	toast::toast,
+	wear::{SaveConfig, Throttle, Wear, read_save_config, write_save_config},
 };
-async fn handle_count_storage(flash: &Mutex<CriticalSectionRawMutex, FlashRegion>) {
-	let mut count_storage = Storage::<u32>::new(0);
-	let saved_count = count_storage.fetch(&mut *flash.lock().await).await;
-	println!("Stored count: {:?}", saved_count);
-	if let Some(c) = saved_count {
-		COUNT.sender().send(c);
-	}
-	let mut stored_count = saved_count;
-	let mut rcv = COUNT.receiver().unwrap();
-	let mut new_count: Option<u32> = None;
+
+/// Signalled when any of the settings or the title change, so they're saved
+pub static CONFIG_UPDATED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// The storage region, shared by the tasks saving the count and the config
+pub type Flash<F> = Mutex<CriticalSectionRawMutex, FlashRegion<F>>;
+
+/// Times reading or writing is tried before giving up until the next save
+const ATTEMPTS: u32 = 4;
+/// Wait before the first retry, which doubles after each one
+const FIRST_BACKOFF: Duration = Duration::from_millis(50);
+/// How long the count waits after it last changed before it's saved, when it isn't busy
+const COUNT_DELAY: Duration = Duration::from_secs(2);
+/// How long the settings wait after they last changed before they're saved, when it isn't busy
+const CONFIG_DELAY: Duration = Duration::from_secs(5);
+/// Longest a change waits to be saved. The delays start again with every change,
+/// so without this something that keeps changing would never be saved
+const MAX_SAVE_DELAY: Duration = Duration::from_secs(5 * 60);
+
+/// When to save: `delay` from now, but no later than `MAX_SAVE_DELAY` after the first change
+/// that hasn't been saved yet (`unsaved_since`)
+fn save_at(delay: Duration, unsaved_since: Option<Instant>) -> Instant {
+	let after_delay = Instant::now() + delay;
+	unsaved_since.map_or(after_delay, |since| after_delay.min(since + MAX_SAVE_DELAY))
+}
+
+/// Shows that something went wrong with storage, without stopping the counter
+fn report(action: &str, e: StorageError<impl Debug>) {
+	println!("Couldn't {action}: {:?}", e);
+	update_diagnostics(|d| d.storage_errors += 1);
+	toast("Storage error");
+}
+
+/// Leaves storage off when the region can't be found, rather than risk writing over something else.
+/// The counter still works, but nothing is loaded or saved
+#[cfg(feature = "device")]
+fn refuse(reason: &str) {
+	println!("Not using storage, as {reason}");
+	update_diagnostics(|d| d.storage_errors += 1);
+	toast("Storage disabled");
+}
+
+/// Erases the region after it's found to be corrupted or full. Returns whether it was erased.
+/// Everything is then saved again from memory (see `erased`)
+async fn recover<F: MultiwriteNorFlash>(flash: &Flash<F>) -> bool {
+	println!("Erasing storage to recover");
+	match flash.lock().await.erase().await {
+		Ok(()) => true,
+		Err(e) => {
+			report("erase storage", e);
+			false
+		}
+	}
+}
+
+/// Whether the region has been erased since `erases` was last updated
+async fn erased<F: MultiwriteNorFlash>(flash: &Flash<F>, erases: &mut u32) -> bool {
+	let now = flash.lock().await.erases();
+	core::mem::replace(erases, now) != now
+}
+
+/// Reads a stored value, retrying with backoff. Returns None if nothing is stored.
+/// A corrupted region is left for the caller to erase, once everything that can be read has been
+async fn load<T: Persisted, F: MultiwriteNorFlash>(
+	storage: &mut Storage<T>,
+	flash: &Flash<F>,
+) -> Result<Option<T>, StorageError<F::Error>> {
+	let mut backoff = FIRST_BACKOFF;
+	let mut attempt = 1;
 	loop {
-		match select(pin!(rcv.changed()), Timer::after_secs(2)).await {
-			// Count changes before timer completes
-			Either::Left((count, _timer)) => new_count = Some(count),
-			// Timer completes before count changes, so save
-			Either::Right(_r) => {
-				if let Some(count) = new_count.take()
-					&& Some(count) != stored_count
-				{
-					println!("Saving count as {count}");
-					count_storage
-						.write(&count, &mut *flash.lock().await)
-						.await
-						.unwrap();
-					stored_count = Some(count);
-					println!("Saved count")
+		let result = storage.fetch(&mut *flash.lock().await).await;
+		match result {
+			// Trying again won't help
+			Err(StorageError::Corrupted) => return result,
+			Err(_) if attempt < ATTEMPTS => {
+				Timer::after(backoff).await;
+				backoff *= 2;
+				attempt += 1;
+			}
+			_ => return result,
+		}
+	}
+}
+
+/// Writes a value, retrying with backoff. A corrupted or full region is erased before trying again.
+/// Returns whether it was saved
+async fn save<T: Persisted, F: MultiwriteNorFlash>(
+	storage: &mut Storage<T>,
+	value: &T,
+	flash: &Flash<F>,
+) -> bool {
+	let mut backoff = FIRST_BACKOFF;
+	for attempt in 1..=ATTEMPTS {
+		let result = storage.write(value, &mut *flash.lock().await).await;
+		match result {
+			Ok(()) => return true,
+			Err(e) if attempt == ATTEMPTS => report("save", e),
+			Err(e) if e.needs_erase() => {
+				recover(flash).await;
+			}
+			Err(_) => {
+				Timer::after(backoff).await;
+				backoff *= 2;
+			}
+		}
+	}
+	false
+}
+
+/// Updates how much of the flash region is used and how worn it is, for the diagnostics screen
+async fn check_usage<F: MultiwriteNorFlash>(flash: &Flash<F>) {
+	let mut flash = flash.lock().await;
+	let (used, size, wear) = (flash.used().await, flash.size(), flash.wear());
+	update_diagnostics(|d| d.wear = wear);
+	match used {
+		Ok(used) => update_diagnostics(|d| {
+			d.flash_used = Some(used);
+			d.flash_size = size;
+		}),
+		Err(e) => report("check flash usage", e),
+	}
+}
+
+/// What happened when a record was saved
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Saved {
+	/// The value was already stored
+	Unchanged,
+	Written,
+	/// It couldn't be saved, so needs trying again
+	Failed,
+}
+
+/// A record, and what's known about the copy of it in flash
+struct Slot<T: Persisted> {
+	storage: Storage<T>,
+	/// The value last read or saved, or None if nothing is stored
+	stored: Option<T>,
+	/// Whether the stored value has been read. Until it has, the record isn't written to,
+	/// so a value that's still in flash isn't replaced by what's in memory
+	loaded: bool,
+	/// Whether reading it has already failed and been reported
+	failed: bool,
+}
+impl<T: Persisted + Clone + PartialEq + Debug> Slot<T> {
+	fn new() -> Self {
+		Self {
+			storage: Storage::new(),
+			stored: None,
+			loaded: false,
+			failed: false,
+		}
+	}
+	/// Reads the record if it hasn't been read yet. Returns what's stored the first time it's read,
+	/// so it can be put to use. Sets `corrupted` if the region needs erasing before it can be read
+	async fn load<F: MultiwriteNorFlash>(
+		&mut self,
+		flash: &Flash<F>,
+		corrupted: &mut bool,
+	) -> Option<T> {
+		if self.loaded {
+			return None;
+		}
+		match load(&mut self.storage, flash).await {
+			Ok(value) => {
+				self.loaded = true;
+				self.failed = false;
+				self.stored = value.clone();
+				value
+			}
+			Err(e) => {
+				*corrupted |= matches!(e, StorageError::Corrupted);
+				// Tried again before each save, so only the first failure is shown
+				if !core::mem::replace(&mut self.failed, true) {
+					report("read storage", e);
 				}
+				None
 			}
 		}
 	}
+	/// Saves `value` unless it's already stored, or what's stored hasn't been read yet
+	async fn save<F: MultiwriteNorFlash>(&mut self, value: &T, flash: &Flash<F>) -> Saved {
+		if !self.loaded {
+			return Saved::Failed;
+		}
+		if self.stored.as_ref() == Some(value) {
+			return Saved::Unchanged;
+		}
+		println!("Saving {:?}", value);
+		if save(&mut self.storage, value, flash).await {
+			self.stored = Some(value.clone());
+			Saved::Written
+		} else {
+			Saved::Failed
+		}
+	}
+	/// Forgets what was stored after the region has been erased, so it's saved again
+	fn erased(&mut self) {
+		self.stored = None;
+		self.loaded = true;
+		self.failed = false;
+	}
 }
 
-async fn handle_config_storage(flash: &Mutex<CriticalSectionRawMutex, FlashRegion>) {
-	let mut config_storage = Storage::<RgbConfig>::new(1);
-	let stored_config = config_storage.fetch(&mut *flash.lock().await).await;
-	if let Some(config) = &stored_config {
-		println!("Stored config: {:?}", config);
-		*RGB_CONFIG.lock().await = config.clone();
+/// The count, tallies and day history, which are saved a couple of seconds after they stop changing
+/// (longer when it's been saving a lot). Also keeps how worn the flash is
+pub struct CountStorage {
+	count: Slot<StoredCount>,
+	legacy_count: Slot<LegacyCount>,
+	tallies: Slot<Tallies>,
+	history: Slot<DayHistory>,
+	wear: Slot<Wear>,
+	throttle: Throttle,
+	/// Erases of the region when it was last saved to
+	erases: u32,
+}
+impl Default for CountStorage {
+	fn default() -> Self {
+		Self::new()
 	}
-	let mut new_config: Option<RgbConfig> = None;
-	let mut stored_config = stored_config;
-	let mut rcv = RGB_CONFIG_UPDATED.receiver().unwrap();
-	loop {
-		match select(pin!(rcv.changed()), Timer::after_secs(5)).await {
-			// Config changes before timer completes
-			Either::Left((_, _timer)) => new_config = Some(RGB_CONFIG.lock().await.clone()),
-			// Timer completes before config changes, so save
-			Either::Right(_r) => {
-				if let Some(config) = new_config.take()
-					&& Some(&config) != stored_config.as_ref()
-				{
-					println!("Saving config as {:?}", config);
-					config_storage
-						.write(&config, &mut *flash.lock().await)
-						.await
-						.unwrap();
-					stored_config = Some(config);
-					println!("Saved config")
+}
+impl CountStorage {
+	pub fn new() -> Self {
+		Self {
+			count: Slot::new(),
+			legacy_count: Slot::new(),
+			tallies: Slot::new(),
+			history: Slot::new(),
+			wear: Slot::new(),
+			throttle: Throttle::new(COUNT_DELAY),
+			erases: 0,
+		}
+	}
+	/// Reads and puts to use anything that hasn't been read yet.
+	/// Returns whether the region is corrupted, so needs erasing
+	pub async fn load<F: MultiwriteNorFlash>(&mut self, flash: &Flash<F>) -> bool {
+		let mut corrupted = false;
+		if let Some(wear) = self.wear.load(flash, &mut corrupted).await {
+			println!("Stored wear: {:?}", wear);
+			flash.lock().await.resume_wear(&wear);
+		}
+		let mut count = self
+			.count
+			.load(flash, &mut corrupted)
+			.await
+			.map(|StoredCount(count)| count);
+		// Only looked for if there's definitely no count stored the current way
+		if self.count.loaded && self.count.stored.is_none() {
+			count = self
+				.legacy_count
+				.load(flash, &mut corrupted)
+				.await
+				.map(Count::from);
+		}
+		if let Some(count) = count {
+			println!("Stored count: {}", count);
+			update_diagnostics(|d| d.stored_count = Some(count));
+			COUNT.sender().send(count);
+		}
+		if let Some(tallies) = self.tallies.load(flash, &mut corrupted).await {
+			println!("Stored tallies: {:?}", tallies);
+			write_tallies(tallies);
+		}
+		// Loaded after the tallies, as today's count is taken from them
+		if let Some(history) = self.history.load(flash, &mut corrupted).await {
+			println!("Stored day history: {:?}", history);
+			restore_day_history(history);
+		}
+		corrupted
+	}
+	/// Saves anything that's changed, or everything if the region has been erased
+	pub async fn save<F: MultiwriteNorFlash>(&mut self, flash: &Flash<F>) {
+		if erased(flash, &mut self.erases).await {
+			self.count.erased();
+			self.tallies.erased();
+			self.history.erased();
+			self.wear.erased();
+		}
+		if let Some(count) = COUNT.try_get()
+			&& self.count.save(&StoredCount(count), flash).await == Saved::Written
+		{
+			update_diagnostics(|d| d.stored_count = Some(count));
+			self.throttle.saved(Instant::now());
+		}
+		// Also saves when the tallies are reset or roll over to a new period
+		self.tallies.save(&read_tallies(), flash).await;
+		// Only saved when a new day starts, as today's count is already in the tallies
+		let history = day_history();
+		if self
+			.history
+			.stored
+			.is_none_or(|stored| stored.latest() != history.latest())
+		{
+			self.history.save(&history, flash).await;
+		}
+		// Only kept when a page has been erased since, as saving it is itself a write
+		let wear = flash.lock().await.wear();
+		if self
+			.wear
+			.stored
+			.is_none_or(|stored| stored.erases != wear.erases)
+		{
+			self.wear.save(&wear, flash).await;
+		}
+		check_usage(flash).await;
+	}
+	/// Saves the count a couple of seconds after it stops changing, along with anything else that's changed.
+	/// If it keeps changing, it's still saved every few minutes (see `MAX_SAVE_DELAY`).
+	/// Anything that couldn't be read is tried again first
+	pub async fn run<F: MultiwriteNorFlash>(&mut self, flash: &Flash<F>) -> ! {
+		let mut rcv = COUNT.receiver().unwrap();
+		let mut unsaved_since = None;
+		loop {
+			let delay = self
+				.throttle
+				.delay(read_save_config().policy, Instant::now());
+			update_diagnostics(|d| d.save_delay = delay);
+			match select(
+				pin!(rcv.changed()),
+				Timer::at(save_at(delay, unsaved_since)),
+			)
+			.await
+			{
+				Either::Left(_) => {
+					unsaved_since.get_or_insert_with(Instant::now);
+				}
+				// Saved once the count stops changing for long enough
+				Either::Right(_) => {
+					if self.load(flash).await {
+						recover(flash).await;
+					}
+					self.save(flash).await;
+					unsaved_since = None;
 				}
 			}
 		}
 	}
 }
+
+/// The settings and title, which are saved a few seconds after they stop changing
+/// (longer when they've been saved a lot)
+pub struct ConfigStorage {
+	rgb_config: Slot<RgbConfig>,
+	stats_config: Slot<StatsConfig>,
+	counter_config: Slot<CounterConfig>,
+	save_config: Slot<SaveConfig>,
+	display_config: Slot<DisplayConfig>,
+	title: Slot<Title>,
+	throttle: Throttle,
+	/// Erases of the region when it was last saved to
+	erases: u32,
+}
+impl Default for ConfigStorage {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+impl ConfigStorage {
+	pub fn new() -> Self {
+		Self {
+			rgb_config: Slot::new(),
+			stats_config: Slot::new(),
+			counter_config: Slot::new(),
+			save_config: Slot::new(),
+			display_config: Slot::new(),
+			title: Slot::new(),
+			throttle: Throttle::new(CONFIG_DELAY),
+			erases: 0,
+		}
+	}
+	/// Reads and puts to use anything that hasn't been read yet.
+	/// Returns whether the region is corrupted, so needs erasing
+	pub async fn load<F: MultiwriteNorFlash>(&mut self, flash: &Flash<F>) -> bool {
+		let mut corrupted = false;
+		if let Some(config) = self.rgb_config.load(flash, &mut corrupted).await {
+			println!("Stored config: {:?}", config);
+			*RGB_CONFIG.lock().await = config;
+		}
+		if let Some(config) = self.stats_config.load(flash, &mut corrupted).await {
+			println!("Stored stats config: {:?}", config);
+			write_stats_config(config);
+		}
+		if let Some(config) = self.counter_config.load(flash, &mut corrupted).await {
+			println!("Stored counter config: {:?}", config);
+			write_counter_config(config);
+		}
+		if let Some(config) = self.save_config.load(flash, &mut corrupted).await {
+			println!("Stored save config: {:?}", config);
+			write_save_config(config);
+		}
+		if let Some(config) = self.display_config.load(flash, &mut corrupted).await {
+			println!("Stored display config: {:?}", config);
+			write_display_config(config);
+			REDRAW.signal(());
+		}
+		if let Some(title) = self.title.load(flash, &mut corrupted).await {
+			println!("Stored title: {:?}", title);
+			write_title(title);
+			REDRAW.signal(());
+		}
+		corrupted
+	}
+	/// Saves anything that's changed, or everything if the region has been erased.
+	/// Returns whether everything is saved
+	pub async fn save<F: MultiwriteNorFlash>(&mut self, flash: &Flash<F>) -> bool {
+		if erased(flash, &mut self.erases).await {
+			self.rgb_config.erased();
+			self.stats_config.erased();
+			self.counter_config.erased();
+			self.save_config.erased();
+			self.display_config.erased();
+			self.title.erased();
+		}
+		let rgb_config = RGB_CONFIG.lock().await.clone();
+		let results = [
+			self.rgb_config.save(&rgb_config, flash).await,
+			self.stats_config.save(&read_stats_config(), flash).await,
+			self.counter_config
+				.save(&read_counter_config(), flash)
+				.await,
+			self.save_config.save(&read_save_config(), flash).await,
+			self.display_config
+				.save(&read_display_config(), flash)
+				.await,
+			self.title.save(&read_title(), flash).await,
+		];
+		// Settings are saved a few seconds after they change, so confirm it happened
+		if results.contains(&Saved::Written) {
+			self.throttle.saved(Instant::now());
+			toast("Saved");
+		}
+		check_usage(flash).await;
+		!results.contains(&Saved::Failed)
+	}
+	/// Saves the settings a few seconds after they stop changing (see `CONFIG_UPDATED`), or every few
+	/// minutes if they keep changing. Anything that couldn't be read or saved is tried again each time
+	pub async fn run<F: MultiwriteNorFlash>(&mut self, flash: &Flash<F>) -> ! {
+		let mut pending = false;
+		let mut unsaved_since = None;
+		loop {
+			let delay = self
+				.throttle
+				.delay(read_save_config().policy, Instant::now());
+			match select(
+				pin!(CONFIG_UPDATED.wait()),
+				Timer::at(save_at(delay, unsaved_since)),
+			)
+			.await
+			{
+				// Config changes before timer completes
+				Either::Left(_) => {
+					pending = true;
+					unsaved_since.get_or_insert_with(Instant::now);
+				}
+				// Timer completes before config changes, so save
+				Either::Right(_) => {
+					if self.load(flash).await {
+						recover(flash).await;
+					}
+					// Everything needs saving again if the count's task erased the region
+					let erased = flash.lock().await.erases() != self.erases;
+					if pending || erased {
+						pending = !self.save(flash).await;
+					}
+					if !pending {
+						unsaved_since = None;
+					}
+				}
+			}
+		}
+	}
+}
+
+/// Loads everything, then saves it as it changes. Everything is read before a corrupted region
+/// is erased, and what could be read is saved again straight away, so it isn't lost.
+/// Generic over the flash so it can be run against a mock (see `mock_flash`)
+pub async fn run_storage<F: MultiwriteNorFlash>(flash: &Flash<F>) -> ! {
+	let mut count = CountStorage::new();
+	let mut config = ConfigStorage::new();
+	// Loaded one after the other, so neither sees the region erased part way through
+	let count_corrupted = count.load(flash).await;
+	let config_corrupted = config.load(flash).await;
+	if (count_corrupted || config_corrupted) && recover(flash).await {
+		count.save(flash).await;
+		config.save(flash).await;
+	}
+	check_usage(flash).await;
+	join(count.run(flash), config.run(flash)).await;
+	unreachable!()
+}
+#[cfg(feature = "device")]
 #[embassy_executor::task]
 pub async fn handle_storage() {
-	// Partition for NVS
-	// boot: ## Label            Usage          Type ST Offset   Length
-	// boot:  0 nvs              WiFi data        01 02 00009000 00006000
-	// So region is 0x9000..0xF000
-	// 0x9000..0xC000 uses half of the NVS region (so the latter region can easily be used in the future to store something else)
-	let flash = Mutex::<CriticalSectionRawMutex, _>::new(FlashRegion::new(
-		FlashStorage::new(),
-		0x9000..0xC000,
-	));
-	join(handle_count_storage(&flash), handle_config_storage(&flash)).await;
-	unreachable!()
+	let mut flash = BlockingAsync::new(FlashStorage::new());
+	// Stored at the start of the NVS partition (0x9000..0xF000 in the default table)
+	let partition = match partitions::find(&mut flash, DATA, NVS).await {
+		Ok(Some(partition)) => partition,
+		Ok(None) => return refuse("there's no NVS partition"),
+		Err(TableError::Flash(e)) => {
+			println!("Couldn't read the partition table: {:?}", e);
+			return refuse("the partition table couldn't be read");
+		}
+		Err(TableError::Checksum) => return refuse("the partition table is damaged"),
+		Err(TableError::OverlapsApp) => return refuse("the NVS partition overlaps the firmware"),
+	};
+	println!(
+		"NVS partition at {:#x}..{:#x}",
+		partition.offset,
+		partition.range().end
+	);
+	update_diagnostics(|d| d.partition_size = Some(partition.size));
+	let region = match FlashRegion::in_partition(flash, partition) {
+		Ok(region) => region,
+		Err(e) => {
+			println!("Storage doesn't fit the NVS partition: {:?}", e);
+			return refuse("the NVS partition doesn't fit");
+		}
+	};
+	let flash = Mutex::<CriticalSectionRawMutex, _>::new(region);
+	run_storage(&flash).await
+}
+
+#[cfg(test)]
+mod tests {
+	use core::task::{Context, Waker};
+	use std::sync::{Mutex as TestLock, PoisonError};
+
+	use embassy_time::MockDriver;
+
+	use super::*;
+	use crate::{const_default::ConstDefault, mock_flash::MockFlash, storage::REGION_SIZE};
+
+	type Mock = MockFlash<{ REGION_SIZE as usize }>;
+
+	/// The tasks share the count and settings, so only one test can run them at a time
+	static LOCK: TestLock<()> = TestLock::new(());
+	/// How far the clock moves between polls
+	const STEP: Duration = Duration::from_millis(10);
+	/// How long the device is left on, which is long enough for anything that's changed to be saved
+	const UPTIME: Duration = Duration::from_secs(60);
+
+	/// Powers the device on with what's in `flash`, and runs storage until it's powered off.
+	/// `change` is called before each poll with how long it's been on, to change the count or settings
+	fn boot(flash: Mock, change: impl FnMut(Duration)) -> Mock {
+		boot_for(flash, UPTIME, change)
+	}
+
+	/// Like `boot`, but powered off after `uptime`
+	fn boot_for(flash: Mock, uptime: Duration, mut change: impl FnMut(Duration)) -> Mock {
+		// Nothing is kept in memory from before it was powered off
+		COUNT.sender().clear();
+		write_title(Title::DEFAULT);
+		let flash = Flash::new(FlashRegion::new(flash, 0));
+		{
+			let mut storage = pin!(run_storage(&flash));
+			let mut cx = Context::from_waker(Waker::noop());
+			let mut on = Duration::from_ticks(0);
+			while on < uptime {
+				change(on);
+				let _ = storage.as_mut().poll(&mut cx);
+				MockDriver::get().advance(STEP);
+				on += STEP;
+			}
+		}
+		flash.into_inner().into_flash()
+	}
+
+	/// Changes the count and title once it's been on for a second, by when everything's loaded
+	fn set(count: Count, title: &'static str) -> impl FnMut(Duration) {
+		move |on| {
+			if on == Duration::from_secs(1) {
+				COUNT.sender().send(count);
+				write_title(Title::new(title));
+				CONFIG_UPDATED.signal(());
+			}
+		}
+	}
+
+	#[test]
+	fn power_cut_while_saving_keeps_a_count() {
+		let _lock = LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+		// Cut during each write and erase of saving the second count in turn, and those after it
+		for cut in 0..12 {
+			let mut flash = boot(Mock::new(), set(5, "Before"));
+			flash.cut_power_after(cut);
+			let mut flash = boot(flash, set(6, "After"));
+			flash.restore_power();
+
+			let flash = boot(flash, |_| {});
+			let count = COUNT.try_get();
+			assert!(
+				matches!(count, Some(5 | 6)),
+				"cut after {cut} writes left the count as {count:?}"
+			);
+			let title = read_title();
+			assert!(
+				matches!(title.as_str(), "Before" | "After"),
+				"cut after {cut} writes left the title as {title:?}"
+			);
+
+			// Saving carries on as normal afterwards
+			let flash = boot(flash, set(7, "Again"));
+			boot(flash, |_| {});
+			assert_eq!(COUNT.try_get(), Some(7), "cut after {cut} writes");
+			assert_eq!(read_title(), Title::new("Again"), "cut after {cut} writes");
+		}
+	}
+
+	#[test]
+	fn flipped_bit_is_recovered_from() {
+		let _lock = LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+		let saved = boot(Mock::new(), set(5, "Bits"));
+		let used = saved.as_bytes().iter().rposition(|&b| b != 0xFF).unwrap() as u32 + 1;
+		for offset in (0..used).step_by(3) {
+			let mut flash = saved.clone();
+			flash.flip_bit(offset, offset as u8);
+
+			// A damaged record is lost, but is never read back as something else
+			let flash = boot(flash, |_| {});
+			let count = COUNT.try_get();
+			assert!(
+				matches!(count, Some(5) | None),
+				"flip at {offset} loaded the count as {count:?}"
+			);
+			let title = read_title();
+			assert!(
+				title == Title::new("Bits") || title == Title::DEFAULT,
+				"flip at {offset} loaded the title as {title:?}"
+			);
+
+			// The region is usable again
+			let flash = boot(flash, set(9, "Fixed"));
+			boot(flash, |_| {});
+			assert_eq!(COUNT.try_get(), Some(9), "flip at {offset}");
+			assert_eq!(read_title(), Title::new("Fixed"), "flip at {offset}");
+		}
+	}
+
+	#[test]
+	fn count_that_keeps_changing_is_still_saved() {
+		let _lock = LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+		// Changes more often than the delay, so it's only saved because of the longest delay.
+		// Powered off before it stops changing
+		let uptime = Duration::from_secs(6 * 60);
+		let flash = boot_for(Mock::new(), uptime, |on| {
+			if on.as_millis() % 1000 == 0 {
+				COUNT.sender().send(on.as_secs() as Count);
+			}
+		});
+		boot(flash, |_| {});
+		let count = COUNT.try_get();
+		assert!(
+			count.is_some_and(|count| count > 0),
+			"nothing was saved, leaving {count:?}"
+		);
+	}
+
+	#[test]
+	fn count_and_config_tasks_share_the_flash() {
+		let _lock = LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+		let titles = ["One", "Two", "Three", "Four"];
+		// Both change every few seconds, so each task often saves while the other is
+		let flash = boot(Mock::new(), |on| {
+			let ms = on.as_millis();
+			if (1000..40_000).contains(&ms) && ms % 2500 == 0 {
+				COUNT.sender().send((ms / 2500) as Count);
+			}
+			if (1000..40_000).contains(&ms) && ms % 6000 == 0 {
+				write_title(Title::new(titles[(ms / 6000) as usize % titles.len()]));
+				CONFIG_UPDATED.signal(());
+			}
+		});
+		boot(flash, |_| {});
+		assert_eq!(COUNT.try_get(), Some(15));
+		assert_eq!(read_title(), Title::new("Three"));
+	}
 }
@@ -1,3 +1,6 @@
+#[cfg(feature = "device")]
+pub mod handle_alarm;
 pub mod handle_button;
+pub mod handle_console;
 pub mod handle_neopixel;
 pub mod handle_storage;
@@ -0,0 +1,60 @@
+//! Widget for typing text one character at a time
+
+use crate::title::TITLE_LEN;
+
+/// Characters that a press cycles through, in order
+const CHARACTERS: &[u8] =
+	b" ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789!?'&+-.:#";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextEntry {
+	/// Characters typed so far, including the one under the cursor
+	chars: [u8; TITLE_LEN],
+	len: usize,
+	/// Position of the character being changed
+	pub cursor: usize,
+}
+impl TextEntry {
+	/// Starts on the first character of `text`. Characters that can't be typed are replaced with '?'
+	pub fn new(text: &str) -> Self {
+		let mut chars = [b' '; TITLE_LEN];
+		let mut len = 0;
+		for (slot, c) in chars.iter_mut().zip(text.chars()) {
+			*slot = if c.is_ascii() && CHARACTERS.contains(&(c as u8)) {
+				c as u8
+			} else {
+				b'?'
+			};
+			len += 1;
+		}
+		Self {
+			chars,
+			// There's always a character under the cursor
+			len: len.max(1),
+			cursor: 0,
+		}
+	}
+	/// Changes the character under the cursor to the next one
+	pub fn cycle(&mut self) {
+		let c = &mut self.chars[self.cursor];
+		let next = CHARACTERS
+			.iter()
+			.position(|x| x == c)
+			.map_or(0, |i| (i + 1) % CHARACTERS.len());
+		*c = CHARACTERS[next];
+	}
+	/// Moves on to the next character, adding a space at the end. Goes back to the start when full
+	pub fn advance(&mut self) {
+		self.cursor = (self.cursor + 1) % TITLE_LEN;
+		self.len = self.len.max(self.cursor + 1);
+	}
+	/// Everything typed, including trailing spaces so the cursor can be shown on them
+	pub fn chars(&self) -> &str {
+		// Only ASCII characters can be typed
+		core::str::from_utf8(&self.chars[..self.len]).unwrap_or_default()
+	}
+	/// The finished text, without trailing spaces
+	pub fn text(&self) -> &str {
+		self.chars().trim_end()
+	}
+}
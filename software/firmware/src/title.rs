@@ -0,0 +1,61 @@
+//! Text shown above the count, which can be changed from the menu or the serial console
+
+use core::{cell::Cell, fmt};
+
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+
+use crate::{const_default::ConstDefault, record::impl_record};
+
+/// Most bytes a title can hold
+pub const TITLE_LEN: usize = 16;
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Title {
+	len: u8,
+	bytes: [u8; TITLE_LEN],
+}
+impl Title {
+	/// Cuts `text` short if it's too long
+	pub const fn new(text: &str) -> Self {
+		let text = text.as_bytes();
+		let mut bytes = [0; TITLE_LEN];
+		let mut len = 0;
+		while len < text.len() && len < TITLE_LEN {
+			bytes[len] = text[len];
+			len += 1;
+		}
+		Self {
+			len: len as u8,
+			bytes,
+		}
+	}
+	pub fn as_str(&self) -> &str {
+		let bytes = &self.bytes[..(self.len as usize).min(TITLE_LEN)];
+		match core::str::from_utf8(bytes) {
+			Ok(text) => text,
+			// Cut short in the middle of a character, so leave that character off
+			Err(e) => core::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap_or_default(),
+		}
+	}
+}
+impl fmt::Debug for Title {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		fmt::Debug::fmt(self.as_str(), f)
+	}
+}
+impl ConstDefault for Title {
+	const DEFAULT: Self = Self::new("Death Toll");
+}
+// Any length or bytes are safe to read back, as `as_str` only shows what's valid
+impl_record!(Title, version 1, [len, bytes]);
+
+pub static TITLE: Mutex<CriticalSectionRawMutex, Cell<Title>> =
+	Mutex::new(Cell::new(Title::DEFAULT));
+
+pub fn read_title() -> Title {
+	TITLE.lock(|t| t.get())
+}
+
+pub fn write_title(title: Title) {
+	TITLE.lock(|t| t.set(title))
+}
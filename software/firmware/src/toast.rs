@@ -0,0 +1,58 @@
+//! Short messages shown over whatever's on the display, so any task can confirm what it's done
+
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
+use embassy_time::{Duration, Instant};
+
+use crate::display::REDRAW;
+
+/// How long each message is shown for
+const TOAST_TIME: Duration = Duration::from_millis(1500);
+/// How long a message can wait to be shown before it's out of date, such as while the display is off
+const TOAST_EXPIRY: Duration = Duration::from_secs(5);
+
+/// Messages waiting to be shown and when they expire, oldest first
+static TOASTS: Channel<CriticalSectionRawMutex, (&'static str, Instant), 4> = Channel::new();
+
+/// Queues a message to be shown once the ones before it have been.
+/// If too many are waiting, the oldest is dropped to make room
+pub fn toast(message: &'static str) {
+	if TOASTS.is_full() {
+		let _ = TOASTS.try_receive();
+	}
+	if TOASTS
+		.try_send((message, Instant::now() + TOAST_EXPIRY))
+		.is_ok()
+	{
+		REDRAW.signal(());
+	}
+}
+
+/// Works through the queued messages one at a time
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Toaster {
+	/// The message being shown, and when it ends
+	current: Option<(&'static str, Instant)>,
+}
+impl Toaster {
+	pub const fn new() -> Self {
+		Self { current: None }
+	}
+	/// The message to show now, moving on to the next one once it's been shown for long enough.
+	/// Messages that expired while waiting are skipped
+	pub fn current(&mut self, now: Instant) -> Option<&'static str> {
+		if self.current.is_none_or(|(_, end)| end <= now) {
+			self.current = None;
+			while let Ok((message, expires)) = TOASTS.try_receive() {
+				if expires > now {
+					self.current = Some((message, now + TOAST_TIME));
+					break;
+				}
+			}
+		}
+		self.current.map(|(message, _)| message)
+	}
+	/// When the message being shown should be taken down
+	pub fn ends_at(&self) -> Option<Instant> {
+		self.current.map(|(_, end)| end)
+	}
+}
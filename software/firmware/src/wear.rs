@@ -0,0 +1,190 @@
+//! Keeps track of how worn the storage region is, and slows down saving when it's being saved often
+
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use embassy_time::{Duration, Instant};
+use embedded_storage_async::nor_flash::{ErrorType, MultiwriteNorFlash, NorFlash, ReadNorFlash};
+use strum::{IntoStaticStr, VariantArray};
+
+use crate::{
+	const_default::ConstDefault,
+	record::{impl_field_for_enum, impl_record},
+	storage::REGION_PAGES,
+};
+
+/// Erase cycles each page of the ESP32-C3's flash is rated for
+pub const ENDURANCE: u32 = 100_000;
+
+/// Erases and writes of each page of the storage region, kept across reboots.
+/// Only saved when a page is erased, so the writes since then are lost if the power goes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Wear {
+	pub erases: [u32; REGION_PAGES],
+	pub writes: [u32; REGION_PAGES],
+}
+impl ConstDefault for Wear {
+	const DEFAULT: Self = Self {
+		erases: [0; REGION_PAGES],
+		writes: [0; REGION_PAGES],
+	};
+}
+impl_record!(Wear, version 1, [erases, writes]);
+
+impl Wear {
+	/// Erases of the page that's been erased the most, which will wear out first
+	pub fn most_erases(&self) -> u32 {
+		self.erases.iter().copied().max().unwrap_or(0)
+	}
+	/// Estimated endurance left in tenths of a percent, going by the most erased page
+	pub fn permille_left(&self) -> u32 {
+		1000 - (self.most_erases().min(ENDURANCE) as u64 * 1000 / ENDURANCE as u64) as u32
+	}
+	/// Adds wear from before, such as what was stored at the last boot
+	pub fn add(&mut self, earlier: &Wear) {
+		for (page, earlier) in self.erases.iter_mut().zip(earlier.erases) {
+			*page = page.saturating_add(earlier);
+		}
+		for (page, earlier) in self.writes.iter_mut().zip(earlier.writes) {
+			*page = page.saturating_add(earlier);
+		}
+	}
+}
+
+/// Flash that counts the erases and writes of each page of the region starting at `start`,
+/// passing everything on to the flash underneath
+pub struct Tracked<'a, F> {
+	flash: &'a mut F,
+	wear: &'a mut Wear,
+	start: u32,
+}
+impl<'a, F: NorFlash> Tracked<'a, F> {
+	pub fn new(flash: &'a mut F, wear: &'a mut Wear, start: u32) -> Self {
+		Self { flash, wear, start }
+	}
+	/// Index of the page that `offset` is in, if it's in the region
+	fn page(&self, offset: u32) -> Option<usize> {
+		let page = offset.checked_sub(self.start)? as usize / F::ERASE_SIZE;
+		(page < REGION_PAGES).then_some(page)
+	}
+}
+impl<F: ErrorType> ErrorType for Tracked<'_, F> {
+	type Error = F::Error;
+}
+impl<F: NorFlash> ReadNorFlash for Tracked<'_, F> {
+	const READ_SIZE: usize = F::READ_SIZE;
+	async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+		self.flash.read(offset, bytes).await
+	}
+	fn capacity(&self) -> usize {
+		self.flash.capacity()
+	}
+}
+impl<F: NorFlash> NorFlash for Tracked<'_, F> {
+	const WRITE_SIZE: usize = F::WRITE_SIZE;
+	const ERASE_SIZE: usize = F::ERASE_SIZE;
+	async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+		// Counted even if it fails, as the page may still have been worn
+		for offset in (from..to).step_by(F::ERASE_SIZE) {
+			if let Some(page) = self.page(offset) {
+				self.wear.erases[page] = self.wear.erases[page].saturating_add(1);
+			}
+		}
+		self.flash.erase(from, to).await
+	}
+	async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+		if let Some(page) = self.page(offset) {
+			self.wear.writes[page] = self.wear.writes[page].saturating_add(1);
+		}
+		self.flash.write(offset, bytes).await
+	}
+}
+impl<F: MultiwriteNorFlash> MultiwriteNorFlash for Tracked<'_, F> {}
+
+/// How long to wait after the last change before saving
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, IntoStaticStr, VariantArray, PartialEq)]
+pub enum SavePolicy {
+	/// Wait longer the more often it's been saving, so a busy day doesn't wear out the flash
+	Adaptive,
+	/// Always save soon after a change, so as little as possible is lost if the power goes
+	Quick,
+	/// Always wait as long as when it's busiest, to make the flash last
+	Sparing,
+}
+impl_field_for_enum!(SavePolicy);
+
+/// How the count and settings are saved, kept as a record of its own
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SaveConfig {
+	pub policy: SavePolicy,
+}
+impl ConstDefault for SaveConfig {
+	const DEFAULT: Self = Self {
+		policy: SavePolicy::Adaptive,
+	};
+}
+impl_record!(SaveConfig, version 1, [policy]);
+
+static SAVE_CONFIG: Mutex<CriticalSectionRawMutex, Cell<SaveConfig>> =
+	Mutex::new(Cell::new(SaveConfig::DEFAULT));
+
+pub fn read_save_config() -> SaveConfig {
+	SAVE_CONFIG.lock(|c| c.get())
+}
+
+pub fn write_save_config(config: SaveConfig) {
+	SAVE_CONFIG.lock(|c| c.set(config))
+}
+
+/// Saves in a window before the delay is doubled, and again for each time as many more
+const BUSY_SAVES: u32 = 30;
+/// Saves are counted over windows this long, each starting from half the count of the one before
+const WINDOW: Duration = Duration::from_secs(600);
+/// Most times the delay can be doubled
+const MAX_DOUBLINGS: u32 = 5;
+
+/// Works out how long to wait before saving, from how often it's been saving lately
+#[derive(Clone, Copy, Debug)]
+pub struct Throttle {
+	/// Delay when it isn't busy
+	base: Duration,
+	/// Saves in the current window, including half of those from before it
+	recent: u32,
+	window_end: Instant,
+}
+impl Throttle {
+	pub fn new(base: Duration) -> Self {
+		Self {
+			base,
+			recent: 0,
+			window_end: Instant::now() + WINDOW,
+		}
+	}
+	/// Moves on to the window that `now` is in
+	fn roll(&mut self, now: Instant) {
+		while self.window_end <= now {
+			self.recent /= 2;
+			self.window_end += WINDOW;
+			// Nothing's left to carry over, so skip straight to the current window
+			if self.recent == 0 && self.window_end <= now {
+				self.window_end = now + WINDOW;
+			}
+		}
+	}
+	/// Records that something was saved
+	pub fn saved(&mut self, now: Instant) {
+		self.roll(now);
+		self.recent += 1;
+	}
+	/// How long to wait after the last change before saving
+	pub fn delay(&mut self, policy: SavePolicy, now: Instant) -> Duration {
+		self.roll(now);
+		let doublings = match policy {
+			SavePolicy::Adaptive => (self.recent / BUSY_SAVES).min(MAX_DOUBLINGS),
+			SavePolicy::Quick => 0,
+			SavePolicy::Sparing => MAX_DOUBLINGS,
+		};
+		self.base * (1 << doublings)
+	}
+}